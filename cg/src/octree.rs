@@ -1,9 +1,10 @@
 //! 高性能的松散八叉树
 
 use std::mem;
+use std::cmp::Ordering;
 
-use {Aabb, Aabb3, Contains};
-use {BaseNum, Point3, Vector3};
+use {Aabb, Aabb3, Contains, Continuous, Discrete, Ray3, SpatialIndex};
+use {BaseFloat, BaseNum, InnerSpace, Point3, Vector3};
 
 use map::{vecmap::VecMap};
 use slab::Slab;
@@ -343,6 +344,114 @@ pub fn collision<A>(
 }
 }
 
+// 通用空间索引接口，句柄即ab节点的id
+impl<S: BaseFloat, T> SpatialIndex<S> for Tree<S, T> {
+type Value = T;
+
+// 分配一个未使用的id并添加
+fn insert(&mut self, aabb: Aabb3<S>, value: T) -> usize {
+	let mut id = self.ab_map.len() + 1;
+	while self.ab_map.get(id).is_some() {
+	id += 1;
+	}
+	self.add(id, aabb, value);
+	id
+}
+fn update(&mut self, handle: usize, aabb: Aabb3<S>) -> bool {
+	Tree::update(self, handle, aabb)
+}
+fn remove(&mut self, handle: usize) -> Option<T> {
+	Tree::remove(self, handle).map(|(_, bind)| bind)
+}
+fn get(&self, handle: usize) -> Option<(&Aabb3<S>, &T)> {
+	Tree::get(self, handle)
+}
+fn len(&self) -> usize {
+	self.ab_map.len()
+}
+fn maintain(&mut self) {
+	self.collect();
+}
+fn query_aabb<F: FnMut(usize, &Aabb3<S>, &T)>(&self, aabb: &Aabb3<S>, mut func: F) {
+	let mut arg = IndexQueryArgs::new(aabb.clone());
+	self.query(aabb, intersects, &mut arg, index_query_func);
+	self.query_outer(&mut arg, index_query_func);
+	for id in arg.result {
+	let ab = unsafe { self.ab_map.get_unchecked(id) };
+	func(id, &ab.aabb, &ab.bind);
+	}
+}
+fn query_ray(&self, ray: &Ray3<S>, max_dist: S) -> Vec<(usize, S)> {
+	let mut arg = RayQueryArgs { ray: ray.clone(), max_dist: max_dist, result: Vec::new() };
+	self.query(ray, ray_oct_func, &mut arg, ray_query_func);
+	self.query_outer(&mut arg, ray_query_func);
+	arg.result.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal));
+	arg.result
+}
+// 每个ab节点查询和自己相交的ab节点，只保留id比自己大的，保证每个碰撞对只调用一次
+fn pairs<F: FnMut(usize, usize)>(&self, mut func: F) {
+	let mut id = 0;
+	for ab in self.ab_map.iter() {
+	id += 1;
+	let ab = match ab {
+		Some(ab) => ab,
+		_ => continue,
+	};
+	let mut arg = IndexQueryArgs::new(ab.aabb.clone());
+	self.query(&ab.aabb, intersects, &mut arg, index_query_func);
+	self.query_outer(&mut arg, index_query_func);
+	for other in arg.result {
+		if other > id {
+		func(id, other);
+		}
+	}
+	}
+}
+}
+
+// 通用空间索引的aabb查询参数
+struct IndexQueryArgs<S: BaseNum> {
+aabb: Aabb3<S>,
+result: Vec<usize>,
+}
+impl<S: BaseNum> IndexQueryArgs<S> {
+fn new(aabb: Aabb3<S>) -> IndexQueryArgs<S> {
+	IndexQueryArgs {
+	aabb: aabb,
+	result: Vec::new(),
+	}
+}
+}
+fn index_query_func<S: BaseNum, T>(arg: &mut IndexQueryArgs<S>, id: usize, aabb: &Aabb3<S>, _bind: &T) {
+if intersects(&arg.aabb, aabb) {
+	arg.result.push(id);
+}
+}
+
+// 通用空间索引的射线查询参数
+struct RayQueryArgs<S: BaseFloat> {
+ray: Ray3<S>,
+max_dist: S,
+result: Vec<(usize, S)>,
+}
+fn ray_oct_func<S: BaseFloat>(ray: &Ray3<S>, aabb: &Aabb3<S>) -> bool {
+ray.intersects(aabb)
+}
+fn ray_query_func<S: BaseFloat, T>(arg: &mut RayQueryArgs<S>, id: usize, aabb: &Aabb3<S>, _bind: &T) {
+// 射线起点在aabb内，距离为0
+let dist = if aabb.contains(&arg.ray.origin) {
+	S::zero()
+} else {
+	match arg.ray.intersection(aabb) {
+	Some(p) => (p - arg.ray.origin).magnitude(),
+	_ => return,
+	}
+};
+if dist <= arg.max_dist {
+	arg.result.push((id, dist));
+}
+}
+
 #[derive(Debug, Clone)]
 struct NodeList {
 head: usize,
@@ -1506,3 +1615,52 @@ println!("-------------------------------------------------------outer:{:?}", tr
 	
 }
 
+#[test]
+fn test_spatial_index(){
+fn query_binds(tree: &Tree<f32, usize>, min: f32, max: f32) -> Vec<usize> {
+	let mut r = Vec::new();
+	SpatialIndex::query_aabb(tree, &Aabb3::new(Point3::new(min, min, min), Point3::new(max, max, max)), |_, _, &bind| r.push(bind));
+	r.sort();
+	r
+}
+let mut tree = Tree::new(Aabb3::new(Point3::new(0f32,0f32,0f32), Point3::new(1000f32,1000f32,1000f32)), 0, 0, 0, 0);
+let mut handles = Vec::new();
+for i in 0..10{
+	let x = i as f32 * 50.0;
+	handles.push(SpatialIndex::insert(&mut tree, Aabb3::new(Point3::new(x, x, x), Point3::new(x + 10.0, x + 10.0, x + 10.0)), i));
+}
+SpatialIndex::maintain(&mut tree);
+assert_eq!(SpatialIndex::len(&tree), 10);
+assert_eq!(query_binds(&tree, 95.0, 105.0), vec![2]);
+
+	// 移动后旧位置查不到，新位置能查到
+	assert!(SpatialIndex::update(&mut tree, handles[2], Aabb3::new(Point3::new(305.0, 305.0, 305.0), Point3::new(315.0, 315.0, 315.0))));
+	SpatialIndex::maintain(&mut tree);
+	assert_eq!(query_binds(&tree, 95.0, 105.0), vec![]);
+	assert_eq!(query_binds(&tree, 300.0, 312.0), vec![2, 6]);
+	assert_eq!(SpatialIndex::get(&tree, handles[2]).map(|(aabb, &bind)| (aabb.min.x, bind)), Some((305.0, 2)));
+	let mut pairs = Vec::new();
+	SpatialIndex::pairs(&tree, |a, b| pairs.push((a, b)));
+	assert_eq!(pairs, vec![(handles[2], handles[6])]);
+
+	// 射线按距离排序
+	let ray = Ray3::new(Point3::new(-100.0, 5.0, 5.0), Vector3::new(1.0, 0.0, 0.0));
+	assert_eq!(SpatialIndex::query_ray(&tree, &ray, 2000.0).iter().map(|&(h, _)| h).collect::<Vec<usize>>(), vec![handles[0]]);
+	let ray = Ray3::new(Point3::new(-100.0, -100.0, -100.0), Vector3::new(1.0, 1.0, 1.0).normalize());
+	let hits = SpatialIndex::query_ray(&tree, &ray, 2000.0);
+	assert_eq!(hits.len(), 10);
+	for i in 1..hits.len() {
+	assert!(hits[i - 1].1 <= hits[i].1);
+	}
+
+	// 删除后句柄失效，新插入的值复用空闲的id
+	assert_eq!(SpatialIndex::remove(&mut tree, handles[6]), Some(6));
+	assert_eq!(SpatialIndex::remove(&mut tree, handles[6]), None);
+	assert!(!SpatialIndex::update(&mut tree, handles[6], Aabb3::new(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0))));
+	SpatialIndex::maintain(&mut tree);
+	assert_eq!(query_binds(&tree, 300.0, 312.0), vec![2]);
+	assert_eq!(SpatialIndex::len(&tree), 9);
+	let h = SpatialIndex::insert(&mut tree, Aabb3::new(Point3::new(1.0, 1.0, 1.0), Point3::new(2.0, 2.0, 2.0)), 100);
+	assert!(!handles.iter().enumerate().any(|(i, &old)| i != 6 && old == h));
+	assert_eq!(query_binds(&tree, 0.0, 5.0), vec![0, 100]);
+}
@@ -0,0 +1,106 @@
+//! [`SpatialIndex`](../trait.SpatialIndex.html) implementation for the DBVT.
+//!
+
+use std::cmp::Ordering;
+
+use cgmath::{BaseFloat, InnerSpace};
+
+use super::{ContinuousVisitor, DiscreteVisitor, DynamicBoundingVolumeTree, TreeValueWrapped};
+use {Aabb3, Ray3, SpatialIndex};
+use algorithm::broad_phase::DbvtBroadPhase;
+use prelude::*;
+
+/// Handles are node indices in the tree. Values are inserted without a fat bound margin.
+///
+/// `insert`, `update` and `remove` refit the tree before returning, so queries always see the
+/// latest bounds and `maintain` has no pending work left.
+impl<S, V> SpatialIndex<S> for DynamicBoundingVolumeTree<TreeValueWrapped<V, Aabb3<S>>>
+where
+    S: BaseFloat,
+    V: Clone,
+{
+    type Value = V;
+
+    fn insert(&mut self, aabb: Aabb3<S>, value: V) -> usize {
+        let handle = DynamicBoundingVolumeTree::insert(self, TreeValueWrapped::from((value, aabb)));
+        self.tick();
+        handle
+    }
+
+    fn update(&mut self, handle: usize, aabb: Aabb3<S>) -> bool {
+        match self.value_index(handle) {
+            Some(value_index) => {
+                self.values[value_index].1.bound = aabb;
+                self.flag_updated(handle);
+                self.tick();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn remove(&mut self, handle: usize) -> Option<V> {
+        match self.value_index(handle) {
+            Some(_) => {
+                let value = DynamicBoundingVolumeTree::remove(self, handle).map(|v| v.value);
+                self.tick();
+                value
+            }
+            None => None,
+        }
+    }
+
+    fn get(&self, handle: usize) -> Option<(&Aabb3<S>, &V)> {
+        self.value_index(handle).map(|value_index| {
+            let v = &self.values[value_index].1;
+            (&v.bound, &v.value)
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    fn maintain(&mut self) {
+        self.tick();
+    }
+
+    fn query_aabb<F>(&self, aabb: &Aabb3<S>, mut func: F)
+    where
+        F: FnMut(usize, &Aabb3<S>, &V),
+    {
+        let mut visitor = DiscreteVisitor::<Aabb3<S>, TreeValueWrapped<V, Aabb3<S>>>::new(aabb);
+        for (value_index, _) in self.query_for_indices(&mut visitor) {
+            let (node_index, ref v) = self.values[value_index];
+            func(node_index, &v.bound, &v.value);
+        }
+    }
+
+    fn query_ray(&self, ray: &Ray3<S>, max_dist: S) -> Vec<(usize, S)> {
+        let mut visitor = ContinuousVisitor::<Ray3<S>, TreeValueWrapped<V, Aabb3<S>>>::new(ray);
+        let mut hits = Vec::default();
+        for (value_index, point) in self.query_for_indices(&mut visitor) {
+            let (node_index, ref v) = self.values[value_index];
+            let dist = if v.bound.contains(&ray.origin) {
+                S::zero()
+            } else {
+                (point - ray.origin).magnitude()
+            };
+            if dist <= max_dist {
+                hits.push((node_index, dist));
+            }
+        }
+        hits.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal));
+        hits
+    }
+
+    fn pairs<F>(&self, mut func: F)
+    where
+        F: FnMut(usize, usize),
+    {
+        let dirty = vec![true; self.values.len()];
+        for (left, right) in DbvtBroadPhase::new().find_collider_pairs(self, &dirty) {
+            func(self.values[left].0, self.values[right].0);
+        }
+    }
+}
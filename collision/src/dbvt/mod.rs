@@ -103,6 +103,7 @@ use prelude::*;
 mod wrapped;
 mod visitor;
 mod util;
+mod index;
 
 const SURFACE_AREA_IMPROVEMENT_FOR_ROTATION: f32 = 0.3;
 const PERFORM_ROTATION_PERCENTAGE: u32 = 10;
@@ -423,9 +424,11 @@ where
     }
 
     /// Return the value index for the given node index.
+    ///
+    /// Returns `None` if the node index is out of range or the node is not a leaf.
     pub fn value_index(&self, node_index: usize) -> Option<usize> {
-        match self.nodes[node_index] {
-            Node::Leaf(ref leaf) => Some(leaf.value),
+        match self.nodes.get(node_index) {
+            Some(&Node::Leaf(ref leaf)) => Some(leaf.value),
             _ => None,
         }
     }
@@ -1,7 +1,8 @@
 //! View frustum for visibility determination
 
-use Plane;
+use {Aabb3, Plane};
 use bound::*;
+use traits::Discrete;
 use cgmath::{Matrix, Matrix4};
 use cgmath::{Ortho, Perspective, PerspectiveFov};
 use cgmath::BaseFloat;
@@ -96,6 +97,12 @@ impl<S: BaseFloat> Frustum<S> {
     }
 }
 
+impl<S: BaseFloat> Discrete<Aabb3<S>> for Frustum<S> {
+    fn intersects(&self, aabb: &Aabb3<S>) -> bool {
+        self.contains(aabb) != Relation::Out
+    }
+}
+
 /// View frustum corner points
 #[derive(Copy, Clone, PartialEq, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
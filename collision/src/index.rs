@@ -0,0 +1,63 @@
+//! Common interface for dynamic spatial indices over axis aligned bounding boxes.
+//!
+//! Implemented for [`DynamicBoundingVolumeTree`](dbvt/struct.DynamicBoundingVolumeTree.html),
+//! so code written against this trait can switch between different index structures.
+//!
+
+use cgmath::BaseFloat;
+
+use {Aabb3, Ray3};
+
+/// A dynamic spatial index storing values together with their bounding box.
+///
+/// Values are addressed by a handle returned from [`insert`](#tymethod.insert), which stays
+/// valid until the value is removed.
+///
+pub trait SpatialIndex<S: BaseFloat> {
+    /// Value stored together with each bounding box
+    type Value;
+
+    /// Insert a value, returning the handle of the inserted value.
+    fn insert(&mut self, aabb: Aabb3<S>, value: Self::Value) -> usize;
+
+    /// Change the bounding box of a value.
+    ///
+    /// Returns false if the handle is not in the index.
+    fn update(&mut self, handle: usize, aabb: Aabb3<S>) -> bool;
+
+    /// Remove a value, returning it if the handle was in the index.
+    fn remove(&mut self, handle: usize) -> Option<Self::Value>;
+
+    /// Get the bounding box and value for the given handle.
+    fn get(&self, handle: usize) -> Option<(&Aabb3<S>, &Self::Value)>;
+
+    /// Number of values in the index.
+    fn len(&self) -> usize;
+
+    /// Return true if the index holds no values.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Bring the internal structure up to date after insert/update/remove.
+    ///
+    /// Should be called once per frame. Implementations must keep queries valid whether or not
+    /// it has been called, so it may only do deferred work such as rebalancing or growing.
+    fn maintain(&mut self);
+
+    /// Visit all values whose bounding box intersects the given bounding box.
+    fn query_aabb<F>(&self, aabb: &Aabb3<S>, func: F)
+    where
+        F: FnMut(usize, &Aabb3<S>, &Self::Value);
+
+    /// Find all values whose bounding box is hit by the ray within `max_dist`.
+    ///
+    /// Returns `(handle, distance)` sorted by increasing distance. The distance is zero when
+    /// the ray starts inside the bounding box.
+    fn query_ray(&self, ray: &Ray3<S>, max_dist: S) -> Vec<(usize, S)>;
+
+    /// Visit every pair of values with intersecting bounding boxes exactly once.
+    fn pairs<F>(&self, func: F)
+    where
+        F: FnMut(usize, usize);
+}
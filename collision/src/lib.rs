@@ -30,6 +30,7 @@ extern crate smallvec;
 pub use bound::*;
 pub use contact::*;
 pub use frustum::*;
+pub use index::SpatialIndex;
 pub use line::*;
 pub use plane::Plane;
 pub use ray::*;
@@ -45,6 +46,7 @@ pub mod algorithm;
 
 mod bound;
 mod frustum;
+mod index;
 mod traits;
 mod plane;
 mod ray;
@@ -99,6 +99,24 @@ impl<S: BaseFloat> Discrete<Sphere<S>> for Sphere<S> {
     }
 }
 
+impl<S: BaseFloat> Discrete<Aabb3<S>> for Sphere<S> {
+    fn intersects(&self, aabb: &Aabb3<S>) -> bool {
+        // closest point of the aabb to the sphere center
+        let p = Point3::new(
+            self.center.x.max(aabb.min.x).min(aabb.max.x),
+            self.center.y.max(aabb.min.y).min(aabb.max.y),
+            self.center.z.max(aabb.min.z).min(aabb.max.z),
+        );
+        p.distance2(self.center) <= self.radius * self.radius
+    }
+}
+
+impl<S: BaseFloat> Discrete<Sphere<S>> for Aabb3<S> {
+    fn intersects(&self, sphere: &Sphere<S>) -> bool {
+        sphere.intersects(self)
+    }
+}
+
 impl<S: BaseFloat> PlaneBound<S> for Sphere<S> {
     fn relate_plane(&self, plane: Plane<S>) -> Relation {
         let dist = self.center.dot(plane.n) - plane.d;
@@ -2,7 +2,7 @@ extern crate cgmath;
 extern crate collision;
 
 use cgmath::{PerspectiveFov, Point3, Rad};
-use collision::{Projection, Relation, Sphere};
+use collision::{Aabb3, Discrete, Projection, Relation, Sphere};
 
 #[test]
 fn test_contains() {
@@ -34,3 +34,25 @@ fn test_contains() {
         Relation::Out
     );
 }
+
+#[test]
+fn test_intersects_aabb() {
+    let frustum = PerspectiveFov {
+        fovy: Rad(1f32),
+        aspect: 1f32,
+        near: 1f32,
+        far: 10f32,
+    }.to_frustum();
+    assert!(frustum.intersects(&Aabb3::new(
+        Point3::new(-1f32, -1f32, -6f32),
+        Point3::new(1f32, 1f32, -4f32),
+    )));
+    assert!(frustum.intersects(&Aabb3::new(
+        Point3::new(-1f32, 2f32, -6f32),
+        Point3::new(1f32, 4f32, -4f32),
+    )));
+    assert!(!frustum.intersects(&Aabb3::new(
+        Point3::new(-1f32, -1f32, 4f32),
+        Point3::new(1f32, 1f32, 6f32),
+    )));
+}
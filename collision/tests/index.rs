@@ -0,0 +1,62 @@
+extern crate cgmath;
+extern crate collision;
+
+use cgmath::{Point3, Vector3};
+use collision::{Aabb3, Ray3, SpatialIndex};
+use collision::dbvt::{DynamicBoundingVolumeTree, TreeValueWrapped};
+
+type Index = DynamicBoundingVolumeTree<TreeValueWrapped<u32, Aabb3<f32>>>;
+
+fn aabb(x: f32, y: f32, z: f32) -> Aabb3<f32> {
+    Aabb3::new(Point3::new(x, y, z), Point3::new(x + 1., y + 1., z + 1.))
+}
+
+fn query<I: SpatialIndex<f32, Value = u32>>(index: &I, x: f32, y: f32, z: f32) -> Vec<u32> {
+    let mut values = Vec::new();
+    index.query_aabb(&aabb(x, y, z), |_, _, &v| values.push(v));
+    values.sort();
+    values
+}
+
+// Only uses the trait methods, maintain is never called
+fn check_without_maintain<I: SpatialIndex<f32, Value = u32>>(index: &mut I) {
+    let handles: Vec<usize> = (0..10)
+        .map(|i| index.insert(aabb(i as f32 * 10., 0., 0.), i))
+        .collect();
+    assert_eq!(index.len(), 10);
+    assert_eq!(query(index, 30., 0., 0.), vec![3]);
+
+    // move a value far away from the others, the parents must follow without maintain
+    assert!(index.update(handles[3], aabb(500., 500., 500.)));
+    assert_eq!(query(index, 30., 0., 0.), Vec::<u32>::new());
+    assert_eq!(query(index, 500., 500., 500.), vec![3]);
+    assert_eq!(index.get(handles[3]).map(|(b, &v)| (b.min, v)), Some((Point3::new(500., 500., 500.), 3)));
+
+    let ray = Ray3::new(Point3::new(500.5, 500.5, -100.), Vector3::new(0., 0., 1.));
+    let hits = index.query_ray(&ray, 1000.);
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].0, handles[3]);
+
+    // move it onto another value, the pair is found without maintain
+    assert!(index.update(handles[3], aabb(70.5, 0., 0.)));
+    let mut pairs = Vec::new();
+    index.pairs(|a, b| pairs.push(if a < b { (a, b) } else { (b, a) }));
+    let expect = if handles[3] < handles[7] { (handles[3], handles[7]) } else { (handles[7], handles[3]) };
+    assert_eq!(pairs, vec![expect]);
+
+    assert_eq!(index.remove(handles[7]), Some(7));
+    assert_eq!(index.remove(handles[7]), None);
+    assert!(!index.update(handles[7], aabb(0., 0., 0.)));
+    assert_eq!(query(index, 70., 0., 0.), vec![3]);
+    assert_eq!(index.len(), 9);
+
+    // out of range handles are not in the index
+    assert!(index.get(10000).is_none());
+    assert!(!index.update(10000, aabb(0., 0., 0.)));
+    assert_eq!(index.remove(10000), None);
+}
+
+#[test]
+fn test_query_without_maintain() {
+    check_without_maintain(&mut Index::new());
+}
@@ -144,6 +144,28 @@ fn test_sphere_contains_aabb() {
     assert!(!sphere.contains(&edge_case));
 }
 
+#[test]
+fn test_sphere_intersects_aabb() {
+    let sphere = Sphere {
+        center: Point3::new(1f32, 2., 3.),
+        radius: 1.,
+    };
+
+    let inside = Aabb3::new(Point3::new(1f32, 2., 3.), Point3::new(1f32, 2.2, 3.));
+    let crossing = Aabb3::new(Point3::new(1.5f32, 2., 3.), Point3::new(11f32, 2.2, 3.));
+    let around = Aabb3::new(Point3::new(-10f32, -10., -10.), Point3::new(10f32, 10., 10.));
+    let outside = Aabb3::new(Point3::new(11f32, 2., 3.), Point3::new(11f32, 2.2, 3.));
+    let corner = Aabb3::new(Point3::new(1.6f32, 2.6, 3.6), Point3::new(2f32, 3., 4.));
+
+    assert!(sphere.intersects(&inside));
+    assert!(sphere.intersects(&crossing));
+    assert!(sphere.intersects(&around));
+    assert!(!sphere.intersects(&outside));
+    assert!(!sphere.intersects(&corner));
+    assert!(crossing.intersects(&sphere));
+    assert!(!corner.intersects(&sphere));
+}
+
 #[test]
 fn test_sphere_union_sphere() {
     let base = Sphere {
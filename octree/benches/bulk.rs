@@ -0,0 +1,74 @@
+#![feature(test)]
+
+extern crate cgmath;
+extern crate collision;
+extern crate octree;
+extern crate rand;
+extern crate test;
+
+use cgmath::Point3;
+use collision::Aabb3;
+use octree::Tree;
+use rand::Rng;
+use test::{black_box, Bencher};
+
+const COUNT: usize = 10000;
+
+fn aabbs() -> Vec<(usize, Aabb3<f32>, usize)> {
+    let mut rng = rand::thread_rng();
+    (0..COUNT)
+        .map(|i| {
+            let x = rng.gen_range(0., 1000.);
+            let y = rng.gen_range(0., 1000.);
+            let z = rng.gen_range(0., 1000.);
+            let s = rng.gen_range(1., 10.);
+            (i + 1, Aabb3::new(Point3::new(x, y, z), Point3::new(x + s, y + s, z + s)), i + 1)
+        })
+        .collect()
+}
+
+fn new_tree() -> Tree<f32, usize> {
+    Tree::new(
+        Aabb3::new(Point3::new(0., 0., 0.), Point3::new(1000., 1000., 1000.)),
+        0,
+        0,
+        0,
+        0,
+    )
+}
+
+#[bench]
+fn benchmark_add(b: &mut Bencher) {
+    let list = aabbs();
+    b.iter(|| {
+        let mut tree = new_tree();
+        for &(id, ref aabb, bind) in list.iter() {
+            tree.add(id, aabb.clone(), bind);
+        }
+        tree.collect();
+        black_box(tree)
+    });
+}
+
+#[bench]
+fn benchmark_add_collect(b: &mut Bencher) {
+    let list = aabbs();
+    b.iter(|| {
+        let mut tree = new_tree();
+        for &(id, ref aabb, bind) in list.iter() {
+            tree.add(id, aabb.clone(), bind);
+            tree.collect();
+        }
+        black_box(tree)
+    });
+}
+
+#[bench]
+fn benchmark_bulk_add(b: &mut Bencher) {
+    let list = aabbs();
+    b.iter(|| {
+        let mut tree = new_tree();
+        tree.bulk_add(list.iter().cloned());
+        black_box(tree)
+    });
+}
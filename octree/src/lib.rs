@@ -5,14 +5,24 @@ extern crate cgmath;
 extern crate collision;
 extern crate map;
 extern crate slab;
+#[cfg(feature = "serialize")]
+#[macro_use]
+extern crate serde;
+#[cfg(feature = "rayon")]
+extern crate rayon;
 use std::mem;
+use std::cmp::Ordering;
+use std::ops::Deref;
+use std::collections::BinaryHeap;
 
 
-use collision::{Aabb, Aabb3, Contains};
-use cgmath::{BaseNum, Point3, Vector3};
+use collision::{Aabb, Aabb3, Contains, Continuous, Discrete, Frustum, Ray3, Relation, SpatialIndex};
+use cgmath::{BaseFloat, BaseNum, InnerSpace, PerspectiveFov, Point3, Rad, Vector3};
 
 use map::{vecmap::VecMap};
 use slab::Slab;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 
 // aabb是否相交
 #[inline]
@@ -49,6 +59,13 @@ if intersects(&arg.aabb, aabb) {
 }
 }
 
+/// 细节层次查询的缩放系数，即透视投影下距离为1时单位长度在屏幕上的像素数，height为屏幕的像素高度
+pub fn lod_scale<S: BaseFloat>(proj: &PerspectiveFov<S>, height: S) -> S {
+	let two = S::one() + S::one();
+	let Rad(fovy) = proj.fovy;
+	height / (two * (fovy / two).tan())
+}
+
 /// OctTree
 pub struct Tree<S: BaseNum, T> {
 	oct_slab: Slab<OctNode<S>>,
@@ -56,9 +73,13 @@ pub struct Tree<S: BaseNum, T> {
 	loose_ratio: usize,     //松散系数，0-10000之间， 默认3000
 	adjust: (usize, usize), //小于min，节点收缩; 大于max，节点分化。默认(4, 5)
 	deep: usize,            // 最大深度
+	max_deep: usize,        // 指定的最大深度，根节点改变时按它重新计算deep
 	loose: Vector3<S>,      //第一层的松散大小
 	outer: NodeList, // 和根节点不相交的ab节点列表，及节点数量。 相交的放在root的nodes上了。 该AbNode的parent为0
 	dirty: (Vec<Vec<usize>>, usize, usize), // 脏的OctNode节点, 及脏节点数量，及脏节点的起始层
+	grow: usize, // 根节点的扩大阈值，outer上的ab节点数量超过该值时，整理时扩大根节点。0表示不扩大
+	grow_fail: bool, // 上次扩大失败，outer上的ab节点变化前不再尝试扩大
+	events: Option<TreeEvents>, // 变化记录，None表示不记录
 }
 
 impl<S: BaseNum, T> Tree<S, T> {
@@ -69,32 +90,17 @@ impl<S: BaseNum, T> Tree<S, T> {
 		adjust_max: usize,
 		deep: usize,
 	) -> Tree<S, T> {
-		let d = root.dim();
 		let loose_ratio = if loose_ratio == 0 { LOOSE } else { loose_ratio };
 		let loose_ratio = if loose_ratio > LOOSE_MAX { LOOSE_MAX } else { loose_ratio };
-		let lr = S::from(loose_ratio).unwrap();
-		let w = S::from(10000).unwrap();
-		let loose = Vector3::new(d.x * lr / w, d.y * lr / w, d.z * lr / w);
-		let mut s = Slab::new();
-		s.insert(OctNode::new(root, loose.clone(), 0, 0, 0));
 		let deep = if deep == 0 || deep > DEEP_MAX {
 			DEEP_MAX
 		} else {
 			deep
 		};
-		let two = S::one() + S::one();
-		let deep = if S::from(1usize).unwrap() / two == S::zero() {
-			// 如果是整数空间，则必须计算最大深度，否则会出现物件不在子节点上
-			let mut i = 0;
-			let mut l = loose / two;
-			while i < deep && l.x > S::zero() && l.y > S::zero() && l.z > S::zero() {
-				l = l / two;
-				i += 1;
-			}
-			i
-		} else {
-			deep
-		};
+		let max_deep = deep;
+		let (loose, deep) = calc_loose(&root, loose_ratio, max_deep);
+		let mut s = Slab::new();
+		s.insert(OctNode::new(root, loose.clone(), 0, 0, 0));
 		let adjust_min = if adjust_min == 0 {
 			ADJUST_MIN
 		} else {
@@ -116,9 +122,51 @@ impl<S: BaseNum, T> Tree<S, T> {
 			loose_ratio: loose_ratio,
 			adjust: (adjust_min, adjust_max),
 			deep: deep,
+			max_deep: max_deep,
 			loose: loose,
 			outer: NodeList::new(),
 			dirty: (Vec::new(), 0, usize::max_value()),
+			grow: 0,
+			grow_fail: false,
+			events: None,
+		}
+	}
+	// 获得根节点的包围盒
+	pub fn get_root(&self) -> &Aabb3<S> {
+		&unsafe { self.oct_slab.get_unchecked(1) }.aabb
+	}
+	// 重设根节点的包围盒，所有的ab节点会按原id重新加入并整理
+	pub fn set_root(&mut self, root: Aabb3<S>) {
+		self.rebuild(root);
+		self.collect();
+	}
+	// 获得根节点的扩大阈值
+	pub fn get_grow(&self) -> usize {
+		self.grow
+	}
+	// 设置根节点的扩大阈值，整理时outer上的ab节点数量超过该值，根节点会向outer上的ab节点成倍扩大。0表示不扩大，默认为0
+	pub fn set_grow(&mut self, grow: usize) {
+		self.grow = grow;
+		self.grow_fail = false;
+	}
+	// 设置是否记录变化，关闭时会清空已有的记录
+	pub fn set_events(&mut self, enable: bool) {
+		if !enable {
+			self.events = None;
+		} else if self.events.is_none() {
+			self.events = Some(TreeEvents::default());
+		}
+	}
+	// 取出上次取出后的变化记录，moved已去重
+	pub fn take_events(&mut self) -> TreeEvents {
+		match self.events {
+			Some(ref mut e) => {
+				let mut r = mem::replace(e, TreeEvents::default());
+				r.moved.sort();
+				r.moved.dedup();
+				r
+			}
+			_ => TreeEvents::default(),
 		}
 	}
 	// 获得松散系数
@@ -140,6 +188,42 @@ impl<S: BaseNum, T> Tree<S, T> {
 			Some(_) => panic!("duplicate id: {}", id),
 			_ => ()
 		}
+		if let Some(ref mut e) = self.events {
+			e.added.push(id);
+		}
+		self.insert_ab(id);
+	}
+	// 批量添加aabb及其绑定，自顶向下一次性分配到八叉节点上并立即分化，得到的结构和逐个添加后整理相同
+	pub fn bulk_add<I>(&mut self, iter: I)
+	where
+		I: IntoIterator<Item = (usize, Aabb3<S>, T)>,
+	{
+		let mut ids = Vec::new();
+		for (id, aabb, bind) in iter {
+			let layer = calc_layer(&self.loose, &aabb.dim());
+			let root = unsafe { self.oct_slab.get_unchecked_mut(1) };
+			let (contains, intersects) = (root.aabb.contains(&aabb), intersects(&root.aabb, &aabb));
+			match self.ab_map.insert(id, AbNode::new(aabb, bind, layer)) {
+				Some(_) => panic!("duplicate id: {}", id),
+				_ => ()
+			}
+			if let Some(ref mut e) = self.events {
+				e.added.push(id);
+			}
+			if contains {
+				ids.push(id);
+			} else if intersects {
+				// 相交的放在root的nodes上
+				push_list(&mut self.ab_map, &mut root.nodes, id, 1, 8);
+			} else {
+				// 和根节点不相交的ab节点, 该AbNode的parent为0
+				push_list(&mut self.ab_map, &mut self.outer, id, 0, 8);
+			}
+		}
+		bulk_down(&mut self.oct_slab, &mut self.ab_map, &self.adjust, self.deep, 1, ids, &mut self.events);
+	}
+	// 将ab_map上的ab节点放入树中
+	fn insert_ab(&mut self, id: usize) {
 		let next = {
 			let node = unsafe { self.ab_map.get_unchecked_mut(id) };
 			let root = unsafe { self.oct_slab.get_unchecked_mut(1) };
@@ -157,6 +241,7 @@ impl<S: BaseNum, T> Tree<S, T> {
 				// 和根节点不相交的ab节点, 该AbNode的parent为0
 				node.next = self.outer.head;
 				self.outer.push(id);
+				self.grow_fail = false;
 			}
 			node.next
 		};
@@ -195,7 +280,11 @@ impl<S: BaseNum, T> Tree<S, T> {
 		}
 		_ => return false,
 		};
+		if r.is_some() {
+			push_moved(&mut self.events, id);
+		}
 		remove_add(self, id, r);
+		self.outer_changed(id);
 		true
 	}
 	// 移动指定id的aabb，性能比update要略好
@@ -215,7 +304,11 @@ impl<S: BaseNum, T> Tree<S, T> {
 		}
 		_ => return false,
 		};
+		if r.is_some() {
+			push_moved(&mut self.events, id);
+		}
 		remove_add(self, id, r);
+		self.outer_changed(id);
 		true
 	}
 	// 获取指定id的可写绑定
@@ -246,6 +339,9 @@ impl<S: BaseNum, T> Tree<S, T> {
 			Some(n) => n,
 			_ => return None
 		};
+		if let Some(ref mut e) = self.events {
+			e.removed.push(id);
+		}
 		if node.parent > 0 {
 		let (p, c) = {
 			let parent = unsafe { self.oct_slab.get_unchecked_mut(node.parent) };
@@ -265,11 +361,15 @@ impl<S: BaseNum, T> Tree<S, T> {
 		} else {
 		// 表示在outer上
 		self.outer.remove(&mut self.ab_map, node.prev, node.next);
+		self.grow_fail = false;
 		}
 		Some((node.aabb, node.bind))
 	}
 	// 整理方法，只有整理方法才会创建或销毁OctNode
 	pub fn collect(&mut self) {
+		if self.grow > 0 && self.outer.len > self.grow && !self.grow_fail {
+			self.grow_root();
+		}
 		let mut count = self.dirty.1;
 		if count == 0 {
 		return;
@@ -288,6 +388,7 @@ impl<S: BaseNum, T> Tree<S, T> {
 			&self.adjust,
 			self.deep,
 			*oct_id,
+			&mut self.events,
 			);
 		}
 		vec.clear();
@@ -299,6 +400,76 @@ impl<S: BaseNum, T> Tree<S, T> {
 		self.dirty.1 = 0;
 		self.dirty.2 = usize::max_value();
 	}
+	// 根节点向outer上的ab节点成倍扩大，直到包含所有outer上的ab节点
+	// 翻倍GROW_MAX次仍不能包含时不扩大，记录失败，outer变化前不再尝试
+	fn grow_root(&mut self) {
+		let mut id = self.outer.head;
+		let mut bound = unsafe { self.ab_map.get_unchecked(id) }.aabb.clone();
+		while id > 0 {
+			let ab = unsafe { self.ab_map.get_unchecked(id) };
+			bound = bound.grow(ab.aabb.min).grow(ab.aabb.max);
+			id = ab.next;
+		}
+		let mut root = self.get_root().clone();
+		let mut i = 0;
+		while i < GROW_MAX && !root.contains(&bound) {
+			// 每次各轴都扩大一倍，保持根节点的比例
+			let d = Vector3::new(
+				grow_step(root.min.x, root.max.x, bound.min.x, bound.max.x),
+				grow_step(root.min.y, root.max.y, bound.min.y, bound.max.y),
+				grow_step(root.min.z, root.max.z, bound.min.z, bound.max.z),
+			);
+			let (min_x, max_x) = if bound.min.x < root.min.x { (root.min.x - d.x, root.max.x) } else { (root.min.x, root.max.x + d.x) };
+			let (min_y, max_y) = if bound.min.y < root.min.y { (root.min.y - d.y, root.max.y) } else { (root.min.y, root.max.y + d.y) };
+			let (min_z, max_z) = if bound.min.z < root.min.z { (root.min.z - d.z, root.max.z) } else { (root.min.z, root.max.z + d.z) };
+			root = Aabb3::new(Point3::new(min_x, min_y, min_z), Point3::new(max_x, max_y, max_z));
+			i += 1;
+		}
+		if !root.contains(&bound) {
+			self.grow_fail = true;
+			return;
+		}
+		self.rebuild(root);
+	}
+	// 更新后的ab节点在outer上，outer的范围可能改变，需要重新尝试扩大
+	#[inline]
+	fn outer_changed(&mut self, id: usize) {
+		if unsafe { self.ab_map.get_unchecked(id) }.parent == 0 {
+			self.grow_fail = false;
+		}
+	}
+	// 用新的根节点重建八叉节点，ab节点的id保持不变
+	fn rebuild(&mut self, root: Aabb3<S>) {
+		let (loose, deep) = calc_loose(&root, self.loose_ratio, self.max_deep);
+		let mut s = Slab::new();
+		s.insert(OctNode::new(root, loose.clone(), 0, 0, 0));
+		self.oct_slab = s;
+		self.loose = loose;
+		self.deep = deep;
+		self.grow_fail = false;
+		self.outer = NodeList::new();
+		self.dirty = (Vec::new(), 0, usize::max_value());
+		let mut ids = Vec::with_capacity(self.ab_map.len());
+		let mut id = 0;
+		for ab in self.ab_map.iter() {
+			id += 1;
+			if ab.is_some() {
+				ids.push(id);
+			}
+		}
+		for id in ids {
+			{
+				let ab = unsafe { self.ab_map.get_unchecked_mut(id) };
+				ab.layer = calc_layer(&self.loose, &ab.aabb.dim());
+				ab.parent = 0;
+				ab.parent_child = 8;
+				ab.prev = 0;
+				ab.next = 0;
+			}
+			push_moved(&mut self.events, id);
+			self.insert_ab(id);
+		}
+	}
 
 	// 查询空间内及相交的ab节点
 	pub fn query<A, B>(
@@ -312,56 +483,739 @@ impl<S: BaseNum, T> Tree<S, T> {
 		&self.oct_slab,
 		&self.ab_map,
 		1,
-		oct_arg,
-		oct_func,
-		ab_arg,
-		ab_func,
+		&mut |aabb| oct_func(oct_arg, aabb),
+		&mut |id, aabb, bind| ab_func(ab_arg, id, aabb, bind),
 		)
 	}
+	// 查询空间内及相交的ab节点，使用闭包
+	pub fn query_with<F, G>(&self, mut oct_func: F, mut ab_func: G)
+	where
+		F: FnMut(&Aabb3<S>) -> bool,
+		G: FnMut(usize, &Aabb3<S>, &T),
+	{
+		query(&self.oct_slab, &self.ab_map, 1, &mut oct_func, &mut ab_func)
+	}
 	// 查询空间外的ab节点
 	pub fn query_outer<B>(
 		&self,
 		arg: &mut B,
 		func: fn(arg: &mut B, id: usize, aabb: &Aabb3<S>, bind: &T),
 	) {
+		self.query_outer_with(|id, aabb, bind| func(arg, id, aabb, bind))
+	}
+	// 查询空间外的ab节点，使用闭包
+	pub fn query_outer_with<F: FnMut(usize, &Aabb3<S>, &T)>(&self, mut func: F) {
 		let mut id = self.outer.head;
 		while id > 0 {
 		let ab = unsafe { self.ab_map.get_unchecked(id) };
-		func(arg, id, &ab.aabb, &ab.bind);
+		func(id, &ab.aabb, &ab.bind);
 		id = ab.next;
 		}
 	}
+	// 查询和指定形状相交的ab节点，包括outer上的ab节点。形状可以是任何实现了和Aabb3相交测试的包围体，如Sphere、Frustum等
+	// 八叉节点用其松散包围盒剔除，ab节点用自身的aabb和形状做相交测试
+	pub fn query_shape<B, A>(
+		&self,
+		shape: &B,
+		arg: &mut A,
+		func: fn(arg: &mut A, id: usize, aabb: &Aabb3<S>, bind: &T),
+	) where
+		B: Discrete<Aabb3<S>>,
+	{
+		self.query_shape_with(shape, |id, aabb, bind| func(arg, id, aabb, bind))
+	}
+	// 查询和指定形状相交的ab节点，使用闭包
+	pub fn query_shape_with<B, F>(&self, shape: &B, mut func: F)
+	where
+		B: Discrete<Aabb3<S>>,
+		F: FnMut(usize, &Aabb3<S>, &T),
+	{
+		let mut ab_func = |id, aabb: &Aabb3<S>, bind: &T| {
+			if shape.intersects(aabb) {
+				func(id, aabb, bind)
+			}
+		};
+		query(&self.oct_slab, &self.ab_map, 1, &mut |aabb: &Aabb3<S>| shape.intersects(aabb), &mut ab_func);
+		self.query_outer_with(ab_func);
+	}
+	// 遍历和指定aabb相交的ab节点的迭代器，包括outer上的ab节点
+	pub fn iter_intersecting<'a>(&'a self, aabb: &Aabb3<S>) -> QueryIter<'a, S, T> {
+		let mut lists = Vec::new();
+		if self.outer.head > 0 {
+			lists.push(self.outer.head);
+		}
+		QueryIter {
+			oct_slab: &self.oct_slab,
+			ab_map: &self.ab_map,
+			aabb: aabb.clone(),
+			octs: vec![1],
+			lists: lists,
+			next: 0,
+		}
+	}
 
-	// 检查碰撞对，不会检查outer的aabb。一般arg包含1个hashset，用(big, little)做键，判断是否已经计算过。
+	// 检查指定id的ab节点的碰撞对，func只会收到aabb相交的ab节点，返回false表示停止检查
+	// limit_layer为None表示不限制，Some(n)只检查层不大于n的ab节点，即忽略更小的ab节点，Some(0)只检查第0层(最大的)ab节点
 	pub fn collision<A>(
 		&self,
 		id: usize,
-		_limit_layer: usize,
+		limit_layer: Option<usize>,
 		arg: &mut A,
 		func: fn(arg: &mut A, a_id: usize, a_aabb: &Aabb3<S>, a_bind: &T, b_id: usize, b_aabb: &Aabb3<S>, b_bind: &T) -> bool,
 	) {
+		self.collision_with(id, limit_layer, |a_id, a_aabb, a_bind, b_id, b_aabb, b_bind| {
+			func(arg, a_id, a_aabb, a_bind, b_id, b_aabb, b_bind)
+		})
+	}
+	// 检查指定id的ab节点的碰撞对，使用闭包
+	pub fn collision_with<F>(&self, id: usize, limit_layer: Option<usize>, mut func: F)
+	where
+		F: FnMut(usize, &Aabb3<S>, &T, usize, &Aabb3<S>, &T) -> bool,
+	{
 		let a = match self.ab_map.get(id) {
 		Some(ab) => ab,
 		_ => return
 		};
-		// 先判断root.nodes是否有节点，如果有则遍历root的nodes
-		let node = unsafe { self.oct_slab.get_unchecked(1) };
-		collision_list(&self.ab_map, id, &a.aabb, &a.bind, arg, func, node.nodes.head);
-		// 和同列表节点碰撞
-		collision_list(&self.ab_map, id, &a.aabb, &a.bind, arg, func, a.next);
-		let mut prev = a.prev;
-		while prev > 0 {
-		let b = unsafe { self.ab_map.get_unchecked(prev) };
-		func(arg, id, &a.aabb, &a.bind, prev, &b.aabb, &b.bind);
-		prev = b.prev;
+		let limit = limit_layer.unwrap_or(usize::max_value());
+		collision_ab(&self.oct_slab, &self.ab_map, &self.outer, id, a, limit, 0, &mut func);
+	}
+	// 检查整个树上的碰撞对，每个aabb相交的碰撞对只会调用一次func，返回false表示停止检查。包括outer上的ab节点
+	// 注意：实现为对每个ab节点从根节点向下做一次碰撞查询(同collision)，开销为O(n·单次查询)，不是一次遍历
+	// 松散八叉树兄弟节点的松散区域互相重叠，只比较祖先链上的列表会漏掉碰撞对，所以每个ab节点都要查询所有重叠的区域
+	// 每帧对大量物体调用时，可只对移动过的ab节点调用collision，并用limit_layer忽略较小的ab节点
+	pub fn collision_pairs<A>(
+		&self,
+		arg: &mut A,
+		func: fn(arg: &mut A, a_id: usize, a_aabb: &Aabb3<S>, a_bind: &T, b_id: usize, b_aabb: &Aabb3<S>, b_bind: &T) -> bool,
+	) {
+		self.collision_pairs_with(|a_id, a_aabb, a_bind, b_id, b_aabb, b_bind| {
+			func(arg, a_id, a_aabb, a_bind, b_id, b_aabb, b_bind)
+		})
+	}
+	// 检查整个树上的碰撞对，使用闭包，开销同collision_pairs
+	pub fn collision_pairs_with<F>(&self, mut func: F)
+	where
+		F: FnMut(usize, &Aabb3<S>, &T, usize, &Aabb3<S>, &T) -> bool,
+	{
+		let max = usize::max_value();
+		// 每个ab节点只和id比自己大的ab节点检查，保证每个碰撞对只检查一次
+		for (_, node) in self.oct_slab.iter() {
+			if !collision_pairs_list(&self.oct_slab, &self.ab_map, &self.outer, node.nodes.head, max, &mut func) {
+				return;
+			}
+			for i in 0..8 {
+				match node.childs[i] {
+					ChildNode::Ab(ref list) if list.head > 0 => {
+						if !collision_pairs_list(&self.oct_slab, &self.ab_map, &self.outer, list.head, max, &mut func) {
+							return;
+						}
+					}
+					_ => (),
+				}
+			}
+		}
+		collision_pairs_list(&self.oct_slab, &self.ab_map, &self.outer, self.outer.head, max, &mut func);
+	}
+	// 导出树的快照，包括八叉节点的布局、松散大小、outer列表及绑定。八叉节点的id会被重新编号，根节点为1
+	pub fn save(&self) -> TreeData<S, T>
+	where
+		T: Clone,
+	{
+		// 旧的八叉节点id到新id的映射，0映射为0
+		let mut ids = Vec::new();
+		let mut octs = Vec::with_capacity(self.oct_slab.len());
+		ids.resize(2, 0);
+		ids[1] = 1;
+		octs.push(1);
+		for (id, _) in self.oct_slab.iter() {
+			if id == 1 {
+				continue;
+			}
+			if ids.len() <= id {
+				ids.resize(id + 1, 0);
+			}
+			octs.push(id);
+			ids[id] = octs.len();
+		}
+		let octs = octs.iter().map(|id| {
+			let mut node = unsafe { self.oct_slab.get_unchecked(*id) }.clone();
+			node.parent = ids[node.parent];
+			for i in 0..8 {
+				if let ChildNode::Oct(ref mut oct, _) = node.childs[i] {
+					*oct = ids[*oct];
+				}
+			}
+			node
+		}).collect();
+		let mut abs = Vec::with_capacity(self.ab_map.len());
+		let mut id = 0;
+		for ab in self.ab_map.iter() {
+			id += 1;
+			if let Some(ab) = ab {
+				let mut ab = ab.clone();
+				ab.parent = ids[ab.parent];
+				abs.push((id, ab));
+			}
+		}
+		// 脏节点也需要重新编号，已被销毁的八叉节点直接丢弃
+		let dirty = self.dirty.0.iter().map(|vec| {
+			vec.iter().filter(|id| **id < ids.len() && ids[**id] > 0).map(|id| ids[*id]).collect()
+		}).collect();
+		TreeData {
+			octs: octs,
+			abs: abs,
+			loose_ratio: self.loose_ratio,
+			adjust: self.adjust,
+			deep: self.deep,
+			max_deep: self.max_deep,
+			loose: self.loose.clone(),
+			outer: self.outer.clone(),
+			dirty: dirty,
+			grow: self.grow,
+		}
+	}
+	// 从快照恢复树，不会重新计算节点的分化和收缩
+	// 快照可能来自外部数据，恢复后先用validate检查所有八叉节点及ab节点的链接和列表长度，不合法时返回Err
+	pub fn load(data: TreeData<S, T>) -> Result<Tree<S, T>, String> {
+		let mut oct_slab = Slab::new();
+		let mut i = 0;
+		for node in data.octs {
+			i += 1;
+			if oct_slab.insert(node) != i {
+				return Err(format!("oct {}: invalid id", i));
+			}
+		}
+		let mut ab_map = VecMap::default();
+		for (id, ab) in data.abs {
+			if id == 0 || ab_map.get(id).is_some() {
+				return Err(format!("ab {}: invalid id", id));
+			}
+			ab_map.insert(id, ab);
+		}
+		let mut count = 0;
+		let mut start = usize::max_value();
+		for (layer, vec) in data.dirty.iter().enumerate() {
+			for id in vec.iter() {
+				if oct_slab.get(*id).is_none() {
+					return Err(format!("dirty oct {} not found", id));
+				}
+			}
+			if vec.len() > 0 && start > layer {
+				start = layer;
+			}
+			count += vec.len();
+		}
+		let tree = Tree {
+			oct_slab: oct_slab,
+			ab_map: ab_map,
+			loose_ratio: data.loose_ratio,
+			adjust: data.adjust,
+			deep: data.deep,
+			max_deep: if data.max_deep == 0 { data.deep } else { data.max_deep },
+			loose: data.loose,
+			outer: data.outer,
+			dirty: (data.dirty, count, start),
+			grow: data.grow,
+			grow_fail: false,
+			events: None,
+		};
+		tree.validate()?;
+		Ok(tree)
+	}
+	// 获得统计信息
+	pub fn stats(&self) -> TreeStats {
+		let mut stats = TreeStats::default();
+		stats.oct_count = self.oct_slab.len();
+		stats.ab_count = self.ab_map.len();
+		stats.outer = self.outer.len;
+		stats.dirty = self.dirty.1;
+		for (_, node) in self.oct_slab.iter() {
+			if stats.max_layer < node.layer {
+				stats.max_layer = node.layer;
+			}
+			// 子八叉节点上的ab节点计入下一层
+			if stats.abs_per_layer.len() < node.layer + 2 {
+				stats.octs_per_layer.resize(node.layer + 2, 0);
+				stats.abs_per_layer.resize(node.layer + 2, 0);
+			}
+			stats.octs_per_layer[node.layer] += 1;
+			stats.abs_per_layer[node.layer] += node.nodes.len;
+			for i in 0..8 {
+				if let ChildNode::Ab(ref list) = node.childs[i] {
+					stats.abs_per_layer[node.layer + 1] += list.len;
+				}
+			}
+		}
+		while stats.octs_per_layer.len() > stats.max_layer + 1 && stats.abs_per_layer.last() == Some(&0) {
+			stats.octs_per_layer.pop();
+			stats.abs_per_layer.pop();
+		}
+		stats
+	}
+	// 检查树的内部状态，包括父子节点的链接、NodeList的prev/next链及ab节点的包围盒是否在所在节点的松散范围内
+	pub fn validate(&self) -> Result<(), String> {
+		let root = match self.oct_slab.get(1) {
+			Some(root) => root,
+			_ => return Err("root not found".to_string()),
+		};
+		if root.parent != 0 || root.layer != 0 {
+			return Err("invalid root".to_string());
+		}
+		let mut count = validate_list(&self.ab_map, 0, 8, &self.outer, |ab| {
+			if intersects(&root.aabb, &ab.aabb) {
+				Err("outer ab intersects root".to_string())
+			} else {
+				Ok(())
+			}
+		})?;
+		for (id, node) in self.oct_slab.iter() {
+			if id > 1 {
+				let parent = match self.oct_slab.get(node.parent) {
+					Some(parent) => parent,
+					_ => return Err(format!("oct {}: parent {} not found", id, node.parent)),
+				};
+				match parent.childs.get(node.parent_child) {
+					Some(&ChildNode::Oct(oct, _)) if oct == id => (),
+					_ => return Err(format!("oct {}: not linked by parent {}", id, node.parent)),
+				}
+				if node.layer != parent.layer + 1 {
+					return Err(format!("oct {}: invalid layer {}", id, node.layer));
+				}
+				if node.aabb != child_aabb(&parent.aabb, &parent.loose, node.parent_child) {
+					return Err(format!("oct {}: invalid aabb", id));
+				}
+			}
+			count += validate_list(&self.ab_map, id, 8, &node.nodes, |ab| {
+				// 根节点的nodes上可以有和根节点相交的ab节点
+				if node.aabb.contains(&ab.aabb) || (id == 1 && intersects(&node.aabb, &ab.aabb)) {
+					Ok(())
+				} else {
+					Err(format!("oct {}: ab not in node", id))
+				}
+			})?;
+			for i in 0..8 {
+				match node.childs[i] {
+					ChildNode::Oct(oct, num) => {
+						match self.oct_slab.get(oct) {
+							Some(child) if child.parent == id && child.parent_child == i => (),
+							_ => return Err(format!("oct {}: invalid child oct {}", id, oct)),
+						}
+						let c = subtree_count(&self.oct_slab, oct)?;
+						if c != num {
+							return Err(format!("oct {}: child oct {} count {}, expect {}", id, oct, num, c));
+						}
+					}
+					ChildNode::Ab(ref list) => {
+						let aabb = child_aabb(&node.aabb, &node.loose, i);
+						count += validate_list(&self.ab_map, id, i, list, |ab| {
+							if ab.layer <= node.layer {
+								Err(format!("oct {}: ab layer {} in child {}", id, ab.layer, i))
+							} else if !aabb.contains(&ab.aabb) {
+								Err(format!("oct {}: ab not in child {}", id, i))
+							} else {
+								Ok(())
+							}
+						})?;
+					}
+				}
+			}
+		}
+		if count != self.ab_map.len() {
+			return Err(format!("ab count {}, expect {}", count, self.ab_map.len()));
+		}
+		Ok(())
+	}
+}
+
+/// 八叉树的变化记录，由Tree::set_events开启
+#[derive(Debug, Clone, Default)]
+pub struct TreeEvents {
+	pub added: Vec<usize>,   // 添加的ab节点
+	pub removed: Vec<usize>, // 移除的ab节点
+	pub moved: Vec<usize>,   // 在update、shift及collect时所在八叉节点改变的ab节点
+}
+
+/// 碰撞对的变化检测，记录上次的碰撞对，和树上当前的碰撞对比较
+#[derive(Debug, Clone, Default)]
+pub struct PairDiff {
+	pairs: Vec<(usize, usize)>, // 上次的碰撞对，小id在前，已排序
+}
+impl PairDiff {
+	pub fn new() -> PairDiff {
+		PairDiff::default()
+	}
+	// 获得上次的碰撞对
+	pub fn pairs(&self) -> &Vec<(usize, usize)> {
+		&self.pairs
+	}
+	// 返回自上次调用后新增的碰撞对和结束的碰撞对，碰撞对的小id在前
+	pub fn diff<S: BaseNum, T>(&mut self, tree: &Tree<S, T>) -> (Vec<(usize, usize)>, Vec<(usize, usize)>) {
+		let mut pairs = Vec::new();
+		tree.collision_pairs_with(|a, _, _, b, _, _| {
+			pairs.push(if a < b { (a, b) } else { (b, a) });
+			true
+		});
+		pairs.sort();
+		let (mut begin, mut end) = (Vec::new(), Vec::new());
+		let (mut i, mut j) = (0, 0);
+		while i < pairs.len() || j < self.pairs.len() {
+			if j == self.pairs.len() || (i < pairs.len() && pairs[i] < self.pairs[j]) {
+				begin.push(pairs[i]);
+				i += 1;
+			} else if i == pairs.len() || self.pairs[j] < pairs[i] {
+				end.push(self.pairs[j]);
+				j += 1;
+			} else {
+				i += 1;
+				j += 1;
+			}
+		}
+		self.pairs = pairs;
+		(begin, end)
+	}
+}
+
+/// 带代数的句柄，index为树上ab节点的id，generation为该id被分配的代数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Handle {
+	index: usize,
+	generation: u32,
+}
+impl Handle {
+	pub fn index(&self) -> usize {
+		self.index
+	}
+	pub fn generation(&self) -> u32 {
+		self.generation
+	}
+}
+
+/// 由树分配句柄的八叉树，移除后的id会被复用，代数加1，旧句柄失效
+/// 查询接口通过Deref使用Tree的，查询结果的id可以用handle方法转为句柄
+pub struct HandleTree<S: BaseNum, T> {
+	tree: Tree<S, T>,
+	generations: Vec<u32>, // 每个id的当前代数，下标为id-1
+	free: Vec<usize>, // 空闲的id
+}
+
+impl<S: BaseNum, T> HandleTree<S, T> {
+	pub fn new(
+		root: Aabb3<S>,
+		loose_ratio: usize,
+		adjust_min: usize,
+		adjust_max: usize,
+		deep: usize,
+	) -> HandleTree<S, T> {
+		HandleTree {
+			tree: Tree::new(root, loose_ratio, adjust_min, adjust_max, deep),
+			generations: Vec::new(),
+			free: Vec::new(),
+		}
+	}
+	// 添加一个aabb及其绑定，返回分配的句柄。优先复用移除的id，保持存储紧凑
+	pub fn add(&mut self, aabb: Aabb3<S>, bind: T) -> Handle {
+		let index = match self.free.pop() {
+			Some(index) => index,
+			_ => {
+				self.generations.push(0);
+				self.generations.len()
+			}
+		};
+		self.tree.add(index, aabb, bind);
+		Handle {
+			index: index,
+			generation: self.generations[index - 1],
+		}
+	}
+	// 句柄是否有效
+	pub fn contains(&self, handle: Handle) -> bool {
+		handle.index > 0
+			&& handle.index <= self.generations.len()
+			&& self.generations[handle.index - 1] == handle.generation
+			&& self.tree.ab_map.get(handle.index).is_some()
+	}
+	// 获得指定id当前的句柄，用于转换查询结果
+	pub fn handle(&self, id: usize) -> Option<Handle> {
+		match self.tree.ab_map.get(id) {
+			Some(_) => Some(Handle {
+				index: id,
+				generation: self.generations[id - 1],
+			}),
+			_ => None,
+		}
+	}
+	// 获取指定句柄的aabb及其绑定，句柄失效返回None
+	pub fn get(&self, handle: Handle) -> Option<(&Aabb3<S>, &T)> {
+		if self.contains(handle) {
+			self.tree.get(handle.index)
+		} else {
+			None
+		}
+	}
+	// 更新指定句柄的aabb，句柄失效返回false
+	pub fn update(&mut self, handle: Handle, aabb: Aabb3<S>) -> bool {
+		self.contains(handle) && self.tree.update(handle.index, aabb)
+	}
+	// 移动指定句柄的aabb，句柄失效返回false
+	pub fn shift(&mut self, handle: Handle, distance: Vector3<S>) -> bool {
+		self.contains(handle) && self.tree.shift(handle.index, distance)
+	}
+	// 更新指定句柄的绑定，句柄失效返回false
+	pub fn update_bind(&mut self, handle: Handle, bind: T) -> bool {
+		self.contains(handle) && self.tree.update_bind(handle.index, bind)
+	}
+	// 移除指定句柄，句柄失效返回None。id放入空闲列表，代数加1
+	pub fn remove(&mut self, handle: Handle) -> Option<(Aabb3<S>, T)> {
+		if !self.contains(handle) {
+			return None;
+		}
+		let r = self.tree.remove(handle.index);
+		let g = &mut self.generations[handle.index - 1];
+		*g = g.wrapping_add(1);
+		self.free.push(handle.index);
+		r
+	}
+	// 整理方法
+	pub fn collect(&mut self) {
+		self.tree.collect();
+	}
+	// 设置根节点的范围
+	pub fn set_root(&mut self, root: Aabb3<S>) {
+		self.tree.set_root(root);
+	}
+	// 设置根节点的扩大阈值
+	pub fn set_grow(&mut self, grow: usize) {
+		self.tree.set_grow(grow);
+	}
+	// 开启或关闭变化记录
+	pub fn set_events(&mut self, enable: bool) {
+		self.tree.set_events(enable);
+	}
+	// 取出变化记录
+	pub fn take_events(&mut self) -> TreeEvents {
+		self.tree.take_events()
+	}
+}
+
+impl<S: BaseNum, T> Deref for HandleTree<S, T> {
+	type Target = Tree<S, T>;
+	fn deref(&self) -> &Tree<S, T> {
+		&self.tree
+	}
+}
+
+/// 八叉树的统计信息
+#[derive(Debug, Clone, Default)]
+pub struct TreeStats {
+	pub oct_count: usize,           // 八叉节点的数量
+	pub ab_count: usize,            // ab节点的数量
+	pub octs_per_layer: Vec<usize>, // 各层的八叉节点数量
+	pub abs_per_layer: Vec<usize>,  // 各层的ab节点数量，子八叉节点上的ab节点计入下一层，不包括outer
+	pub outer: usize,               // outer上的ab节点数量
+	pub max_layer: usize,           // 八叉节点达到的最大层
+	pub dirty: usize,               // 脏八叉节点的数量
+}
+
+/// 八叉树的快照，由Tree::save导出，Tree::load恢复。开启serialize特性后可以序列化
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct TreeData<S: BaseNum, T> {
+	octs: Vec<OctNode<S>>,          // 八叉节点，按新id的顺序排列
+	abs: Vec<(usize, AbNode<S, T>)>, // ab节点及其id
+	loose_ratio: usize,
+	adjust: (usize, usize),
+	deep: usize,
+	#[cfg_attr(feature = "serialize", serde(default))]
+	max_deep: usize, // 旧的快照没有该字段，为0时使用deep
+	loose: Vector3<S>,
+	outer: NodeList,
+	dirty: Vec<Vec<usize>>, // 各层的脏八叉节点
+	grow: usize,
+}
+
+impl<S: BaseFloat, T> Tree<S, T> {
+	// 查询离指定点最近的k个ab节点，返回(id, 距离, 绑定)，按距离从近到远排列。包括outer上的ab节点
+	pub fn query_nearest(&self, point: &Point3<S>, k: usize) -> Vec<(usize, S, &T)> {
+		nearest(&self.oct_slab, &self.ab_map, &self.outer, point, k, S::infinity())
+	}
+	// 查询指定半径内离指定点最近的k个ab节点，k为0表示不限数量
+	pub fn query_nearest_within(&self, point: &Point3<S>, radius: S, k: usize) -> Vec<(usize, S, &T)> {
+		let k = if k == 0 { usize::max_value() } else { k };
+		nearest(&self.oct_slab, &self.ab_map, &self.outer, point, k, radius * radius)
+	}
+	// 射线查询，返回max_dist内和射线相交的ab节点(id, 距离, 绑定)，按距离从近到远排列。包括outer上的ab节点
+	pub fn raycast(&self, ray: &Ray3<S>, max_dist: S) -> Vec<(usize, S, &T)> {
+		best_first(&self.oct_slab, &self.ab_map, &self.outer, usize::max_value(), max_dist, |aabb| ray_distance(ray, aabb))
+	}
+	// 射线查询，返回max_dist内第一个和射线相交的ab节点，找到后立即停止遍历
+	pub fn raycast_first(&self, ray: &Ray3<S>, max_dist: S) -> Option<(usize, S, &T)> {
+		best_first(&self.oct_slab, &self.ab_map, &self.outer, 1, max_dist, |aabb| ray_distance(ray, aabb)).pop()
+	}
+	// 视锥体查询，遍历视锥体内及相交的ab节点。包括outer上的ab节点
+	// 完全在视锥体内的八叉节点，其下的ab节点不再检查，直接遍历
+	pub fn query_frustum<B>(
+		&self,
+		frustum: &Frustum<S>,
+		arg: &mut B,
+		func: fn(arg: &mut B, id: usize, aabb: &Aabb3<S>, bind: &T),
+	) {
+		self.query_frustum_with(frustum, |id, aabb, bind| func(arg, id, aabb, bind))
+	}
+	// 视锥体查询，使用闭包
+	pub fn query_frustum_with<F: FnMut(usize, &Aabb3<S>, &T)>(&self, frustum: &Frustum<S>, mut func: F) {
+		// 根节点的nodes上有超出根节点范围的ab节点，所以根节点按相交处理
+		frustum_query(&self.oct_slab, &self.ab_map, 1, frustum, Relation::Cross, &mut func);
+		frustum_list(&self.ab_map, frustum, Relation::Cross, self.outer.head, &mut func);
+	}
+	// 细节层次查询，遍历和形状相交、且从eye看投影大小不小于min_size像素的ab节点。包括outer上的ab节点
+	// ab节点的大小为aabb的对角线长度，投影大小为 大小 * scale / 到eye的距离，scale可用lod_scale计算
+	// 子八叉节点下ab节点的大小不超过父节点的松散值，所以远处的深层节点会被整个跳过
+	pub fn query_lod<B, A>(
+		&self,
+		shape: &B,
+		eye: &Point3<S>,
+		scale: S,
+		min_size: S,
+		arg: &mut A,
+		func: fn(arg: &mut A, id: usize, aabb: &Aabb3<S>, bind: &T),
+	) where
+		B: Discrete<Aabb3<S>>,
+	{
+		self.query_lod_with(shape, eye, scale, min_size, |id, aabb, bind| func(arg, id, aabb, bind))
+	}
+	// 细节层次查询，使用闭包
+	pub fn query_lod_with<B, F>(&self, shape: &B, eye: &Point3<S>, scale: S, min_size: S, mut func: F)
+	where
+		B: Discrete<Aabb3<S>>,
+		F: FnMut(usize, &Aabb3<S>, &T),
+	{
+		let lod = Lod {
+			eye: eye.clone(),
+			scale2: scale * scale,
+			min2: min_size * min_size,
+		};
+		lod_query(&self.oct_slab, &self.ab_map, 1, shape, &lod, &mut func);
+		lod_list(&self.ab_map, shape, &lod, self.outer.head, &mut func);
+	}
+}
+
+// 通用空间索引接口，句柄即ab节点的id
+impl<S: BaseFloat, T> SpatialIndex<S> for Tree<S, T> {
+	type Value = T;
+
+	// 分配一个未使用的id并添加
+	fn insert(&mut self, aabb: Aabb3<S>, value: T) -> usize {
+		let mut id = self.ab_map.len() + 1;
+		while self.ab_map.get(id).is_some() {
+			id += 1;
+		}
+		self.add(id, aabb, value);
+		id
+	}
+	fn update(&mut self, handle: usize, aabb: Aabb3<S>) -> bool {
+		Tree::update(self, handle, aabb)
+	}
+	fn remove(&mut self, handle: usize) -> Option<T> {
+		Tree::remove(self, handle).map(|(_, bind)| bind)
+	}
+	fn get(&self, handle: usize) -> Option<(&Aabb3<S>, &T)> {
+		Tree::get(self, handle)
+	}
+	fn len(&self) -> usize {
+		self.ab_map.len()
+	}
+	fn maintain(&mut self) {
+		self.collect();
+	}
+	fn query_aabb<F: FnMut(usize, &Aabb3<S>, &T)>(&self, aabb: &Aabb3<S>, mut func: F) {
+		for (id, a, bind) in self.iter_intersecting(aabb) {
+			func(id, a, bind);
+		}
+	}
+	fn query_ray(&self, ray: &Ray3<S>, max_dist: S) -> Vec<(usize, S)> {
+		self.raycast(ray, max_dist).into_iter().map(|(id, dist, _)| (id, dist)).collect()
+	}
+	fn pairs<F: FnMut(usize, usize)>(&self, mut func: F) {
+		self.collision_pairs_with(|a, _, _, b, _, _| {
+			func(a, b);
+			true
+		});
+	}
+}
+
+// 只读的批量查询，查询期间树不能被修改，多个查询在rayon的线程池中并行执行
+#[cfg(feature = "rayon")]
+impl<S: BaseFloat + Send + Sync, T: Sync> Tree<S, T> {
+	// 批量aabb查询，每个aabb返回一个和其相交的ab节点(id, 绑定)列表。包括outer上的ab节点
+	pub fn par_query(&self, aabbs: &[Aabb3<S>]) -> Vec<Vec<(usize, &T)>> {
+		aabbs.par_iter().map(|aabb| self.iter_intersecting(aabb).map(|(id, _, bind)| (id, bind)).collect()).collect()
+	}
+	// 批量形状查询，每个形状返回一个和其相交的ab节点(id, 绑定)列表
+	pub fn par_query_shape<B: Discrete<Aabb3<S>> + Sync>(&self, shapes: &[B]) -> Vec<Vec<(usize, &T)>> {
+		shapes.par_iter().map(|shape| {
+			let mut ids = Vec::new();
+			self.query_shape_with(shape, |id, _, _| ids.push(id));
+			ids.into_iter().filter_map(|id| self.ab_map.get(id).map(|ab| (id, &ab.bind))).collect()
+		}).collect()
+	}
+	// 批量射线查询，每条射线返回max_dist内相交的ab节点(id, 距离, 绑定)，按距离从近到远排列
+	pub fn par_raycast(&self, rays: &[Ray3<S>], max_dist: S) -> Vec<Vec<(usize, S, &T)>> {
+		rays.par_iter().map(|ray| self.raycast(ray, max_dist)).collect()
+	}
+}
+
+/// 和指定aabb相交的ab节点的迭代器
+pub struct QueryIter<'a, S: BaseNum + 'a, T: 'a> {
+	oct_slab: &'a Slab<OctNode<S>>,
+	ab_map: &'a VecMap<AbNode<S, T>>,
+	aabb: Aabb3<S>,
+	octs: Vec<usize>,  // 待遍历的八叉节点
+	lists: Vec<usize>, // 待遍历的ab节点列表的头
+	next: usize,       // 当前列表上的下一个ab节点
+}
+impl<'a, S: BaseNum, T> Iterator for QueryIter<'a, S, T> {
+	type Item = (usize, &'a Aabb3<S>, &'a T);
+	fn next(&mut self) -> Option<Self::Item> {
+		loop {
+			while self.next > 0 {
+				let id = self.next;
+				let ab = unsafe { self.ab_map.get_unchecked(id) };
+				self.next = ab.next;
+				if intersects(&self.aabb, &ab.aabb) {
+					return Some((id, &ab.aabb, &ab.bind));
+				}
+			}
+			if let Some(head) = self.lists.pop() {
+				self.next = head;
+				continue;
+			}
+			let oct_id = match self.octs.pop() {
+				Some(id) => id,
+				_ => return None,
+			};
+			let node = unsafe { self.oct_slab.get_unchecked(oct_id) };
+			self.next = node.nodes.head;
+			for i in 0..8 {
+				match node.childs[i] {
+					ChildNode::Oct(oct, num) if num > 0 => {
+						if intersects(&self.aabb, &child_aabb(&node.aabb, &node.loose, i)) {
+							self.octs.push(oct);
+						}
+					}
+					ChildNode::Ab(ref list) if list.head > 0 => {
+						if intersects(&self.aabb, &child_aabb(&node.aabb, &node.loose, i)) {
+							self.lists.push(list.head);
+						}
+					}
+					_ => (),
+				}
+			}
 		}
-		
-		// 需要计算是否在重叠区，如果在，则需要上溯检查重叠的兄弟节点。不在，其实也需要上溯检查父的匹配节点，但可以提前计算ab节点的最小层
-		//}
 	}
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 struct NodeList {
 head: usize,
 len: usize,
@@ -397,8 +1251,10 @@ const LOOSE_MAX: usize = 5000;
 const DEEP_MAX: usize = 24;
 const ADJUST_MIN: usize = 4;
 const ADJUST_MAX: usize = 5;
+const GROW_MAX: usize = 16; // 根节点每次扩大时最多翻倍的次数
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 struct OctNode<S: BaseNum> {
 	aabb: Aabb3<S>,         // 包围盒
 	loose: Vector3<S>,      // 本层的松散值
@@ -440,12 +1296,14 @@ impl<S: BaseNum> OctNode<S> {
 	}
 }
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 enum ChildNode {
 	Oct(usize, usize), // 对应的OctNode, 及其下ab节点的数量
 	Ab(NodeList),      // ab节点列表，及节点数量
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 struct AbNode<S: BaseNum, T> {
 	aabb: Aabb3<S>,      // 包围盒
 	bind: T,             // 绑定
@@ -469,6 +1327,44 @@ impl<S: BaseNum, T> AbNode<S, T> {
 	}
 }
 
+// 计算根节点扩大时一个轴上每次扩大的大小，为根节点在该轴的长度，即扩大一倍
+// 长度为0的轴(如平面的根节点)无法倍增，改为扩大到能包含bound在该轴的范围
+fn grow_step<S: BaseNum>(root_min: S, root_max: S, min: S, max: S) -> S {
+	let step = root_max - root_min;
+	if step > S::zero() {
+		return step;
+	}
+	let mut step = max - min;
+	if min < root_min && step < root_min - min {
+		step = root_min - min;
+	}
+	if max > root_max && step < max - root_max {
+		step = max - root_max;
+	}
+	step
+}
+
+// 计算第一层的松散大小及最大深度
+fn calc_loose<S: BaseNum>(root: &Aabb3<S>, loose_ratio: usize, deep: usize) -> (Vector3<S>, usize) {
+	let d = root.dim();
+	let lr = S::from(loose_ratio).unwrap();
+	let w = S::from(10000).unwrap();
+	let loose = Vector3::new(d.x * lr / w, d.y * lr / w, d.z * lr / w);
+	let two = S::one() + S::one();
+	let deep = if S::from(1usize).unwrap() / two == S::zero() {
+		// 如果是整数空间，则必须计算最大深度，否则会出现物件不在子节点上
+		let mut i = 0;
+		let mut l = loose / two;
+		while i < deep && l.x > S::zero() && l.y > S::zero() && l.z > S::zero() {
+			l = l / two;
+			i += 1;
+		}
+		i
+	} else {
+		deep
+	};
+	(loose, deep)
+}
 // 计算该aabb对应的层
 #[inline]
 fn calc_layer<S: BaseNum>(loose: &Vector3<S>, el: &Vector3<S>) -> usize {
@@ -702,8 +1598,8 @@ fn update<S: BaseNum, T>(
 					node.parent_child = child;
 					node.next = list.head;
 					list.push(id);
-					if list.len > adjust.1 && node.layer < deep {
-					set_dirty(&mut parent.dirty, child, parent.layer, id);
+					if list.len > adjust.1 && parent.layer < deep {
+					set_tree_dirty(dirty, set_dirty(&mut parent.dirty, child, parent.layer, old_p));
 					}
 					return Some((old_p, old_c, prev, next, node.next));
 				}
@@ -822,6 +1718,7 @@ pub fn remove_add<S: BaseNum, T>(
 			}
 		} else {
 			tree.outer.remove(&mut tree.ab_map, prev, next);
+			tree.grow_fail = false;
 		}
 		if cur_next > 0 {
 			let n = unsafe { tree.ab_map.get_unchecked_mut(cur_next) };
@@ -889,21 +1786,77 @@ fn set_tree_dirty(dirty: &mut (Vec<Vec<usize>>, usize, usize), (layer, rid): (us
 	vec.push(rid);
 }
 
-// 创建指定的子节点
-fn create_child<S: BaseNum>(
-	aabb: &Aabb3<S>,
-	loose: &Vector3<S>,
-	layer: usize,
-	parent_id: usize,
-child: usize) -> OctNode<S> {
-	let two = S::one() + S::one();
-	let x1 = (aabb.min.x + aabb.max.x - loose.x) / two;
-	let y1 = (aabb.min.y + aabb.max.y - loose.y) / two;
+// 检查ab节点列表，返回列表长度
+fn validate_list<S: BaseNum, T, F>(
+	ab_map: &VecMap<AbNode<S, T>>,
+	parent: usize,
+	parent_child: usize,
+	list: &NodeList,
+	func: F,
+) -> Result<usize, String>
+where
+	F: Fn(&AbNode<S, T>) -> Result<(), String>,
+{
+	let mut id = list.head;
+	let mut prev = 0;
+	let mut i = 0;
+	while id > 0 {
+		let ab = match ab_map.get(id) {
+			Some(ab) => ab,
+			_ => return Err(format!("ab {} not found", id)),
+		};
+		if ab.prev != prev {
+			return Err(format!("ab {}: prev {}, expect {}", id, ab.prev, prev));
+		}
+		if ab.parent != parent || ab.parent_child != parent_child {
+			return Err(format!("ab {}: parent ({}, {}), expect ({}, {})", id, ab.parent, ab.parent_child, parent, parent_child));
+		}
+		func(ab).map_err(|e| format!("ab {}: {}", id, e))?;
+		i += 1;
+		if i > list.len {
+			return Err(format!("ab {}: list longer than {}", id, list.len));
+		}
+		prev = id;
+		id = ab.next;
+	}
+	if i != list.len {
+		return Err(format!("list of ({}, {}): len {}, expect {}", parent, parent_child, list.len, i));
+	}
+	Ok(i)
+}
+// 计算八叉节点下ab节点的数量，子八叉节点不存在或链接错误时返回Err。子节点的层必须比父节点大1，所以不会死循环
+fn subtree_count<S: BaseNum>(oct_slab: &Slab<OctNode<S>>, oct_id: usize) -> Result<usize, String> {
+	let node = match oct_slab.get(oct_id) {
+		Some(node) => node,
+		_ => return Err(format!("oct {} not found", oct_id)),
+	};
+	let mut count = node.nodes.len;
+	for i in 0..8 {
+		count += match node.childs[i] {
+			ChildNode::Oct(oct, _) => {
+				match oct_slab.get(oct) {
+					Some(child) if child.parent == oct_id && child.parent_child == i && child.layer == node.layer + 1 => (),
+					_ => return Err(format!("oct {}: invalid child oct {}", oct_id, oct)),
+				}
+				subtree_count(oct_slab, oct)?
+			}
+			ChildNode::Ab(ref list) => list.len,
+		};
+	}
+	Ok(count)
+}
+
+// 计算指定子节点的松散包围盒
+#[inline]
+fn child_aabb<S: BaseNum>(aabb: &Aabb3<S>, loose: &Vector3<S>, child: usize) -> Aabb3<S> {
+	let two = S::one() + S::one();
+	let x1 = (aabb.min.x + aabb.max.x - loose.x) / two;
+	let y1 = (aabb.min.y + aabb.max.y - loose.y) / two;
 	let z1 = (aabb.min.z + aabb.max.z - loose.z) / two;
 	let x2 = (aabb.min.x + aabb.max.x + loose.x) / two;
 	let y2 = (aabb.min.y + aabb.max.y + loose.y) / two;
 	let z2 = (aabb.min.z + aabb.max.z + loose.z) / two;
-	let a = match child {
+	match child {
 		0 => Aabb3::new(Point3::new(x1, y1, z1), aabb.max()),
 		1 => Aabb3::new(
 			Point3::new(aabb.min.x, y1, z1),
@@ -930,8 +1883,18 @@ child: usize) -> OctNode<S> {
 			Point3::new(aabb.max.x, y2, z2),
 		),
 		_ => Aabb3::new(aabb.min(), Point3::new(x2, y2, z2)),
-	};
-	return OctNode::new(a, loose / two, parent_id, child, layer+1);
+	}
+}
+
+// 创建指定的子节点
+fn create_child<S: BaseNum>(
+	aabb: &Aabb3<S>,
+	loose: &Vector3<S>,
+	layer: usize,
+	parent_id: usize,
+child: usize) -> OctNode<S> {
+	let two = S::one() + S::one();
+	return OctNode::new(child_aabb(aabb, loose, child), loose / two, parent_id, child, layer+1);
 }
 
 // 整理方法，只有整理方法才会创建或销毁OctNode
@@ -941,6 +1904,7 @@ fn collect<S: BaseNum, T>(
 	adjust: &(usize, usize),
 	deep: usize,
 	parent_id: usize,
+	events: &mut Option<TreeEvents>,
 ) {
 	let (dirty, childs, ab, loose, layer) = {
 		let parent = match oct_slab.get_mut(parent_id) {
@@ -961,14 +1925,13 @@ fn collect<S: BaseNum, T>(
 			match childs[i] {
 				ChildNode::Oct(oct, num) if num < adjust.0 => {
 				let mut list = NodeList::new();
-				if num > 0 {
-					shrink(oct_slab, ab_map, parent_id, i, oct, &mut list);
-				}
+				// 没有ab节点的子八叉节点也需要销毁
+				shrink(oct_slab, ab_map, parent_id, i, oct, &mut list, events);
 				let parent = unsafe { oct_slab.get_unchecked_mut(parent_id) };
 				parent.childs[i] = ChildNode::Ab(list);
 				}
 				ChildNode::Ab(ref list) if list.len > adjust.1 => {
-				let child_id = split(oct_slab, ab_map, adjust, deep, list, &ab, &loose, layer, parent_id, i);
+				let child_id = split(oct_slab, ab_map, adjust, deep, list, &ab, &loose, layer, parent_id, i, events);
 				let parent = unsafe { oct_slab.get_unchecked_mut(parent_id) };
 				parent.childs[i] = ChildNode::Oct(child_id, list.len);
 				}
@@ -985,20 +1948,21 @@ fn shrink<S: BaseNum, T>(
 	parent_child: usize,
 	oct_id: usize,
 	result: &mut NodeList,
+	events: &mut Option<TreeEvents>,
 ) {
 	let node =oct_slab.remove(oct_id);
 	if node.nodes.len > 0 {
-		shrink_merge(ab_map, parent, parent_child, &node.nodes, result);
+		shrink_merge(ab_map, parent, parent_child, &node.nodes, result, events);
 	}
 	#[macro_use()]
 	macro_rules! child_macro {
 		($i:tt) => {
 		match node.childs[$i] {
 			ChildNode::Ab(ref list) if list.len > 0 => {
-			shrink_merge(ab_map, parent, parent_child, &list, result);
+			shrink_merge(ab_map, parent, parent_child, &list, result, events);
 			}
-			ChildNode::Oct(oct, len) if len > 0 => {
-			shrink(oct_slab, ab_map, parent, parent_child, oct, result);
+			ChildNode::Oct(oct, _) => {
+			shrink(oct_slab, ab_map, parent, parent_child, oct, result, events);
 			}
 			_ => (),
 		}
@@ -1013,6 +1977,13 @@ fn shrink<S: BaseNum, T>(
 	child_macro!(6);
 	child_macro!(7);
 }
+// 记录所在八叉节点改变的ab节点
+#[inline]
+fn push_moved(events: &mut Option<TreeEvents>, id: usize) {
+	if let Some(ref mut e) = *events {
+		e.moved.push(id);
+	}
+}
 // 合并ab列表到结果列表中
 #[inline]
 fn shrink_merge<S: BaseNum, T>(
@@ -1021,12 +1992,14 @@ fn shrink_merge<S: BaseNum, T>(
 	parent_child: usize,
 	list: &NodeList,
 	result: &mut NodeList,
+	events: &mut Option<TreeEvents>,
 ) {
 	let old = result.head;
 	result.head = list.head;
 	result.len += list.len;
 	let mut id = list.head;
 	loop {
+		push_moved(events, id);
 		let ab = unsafe { ab_map.get_unchecked_mut(id) };
 		ab.parent = parent;
 		ab.parent_child = parent_child;
@@ -1055,12 +2028,13 @@ fn split<S: BaseNum, T>(
 	parent_layer: usize,
 	parent_id: usize,
 	child: usize,
+	events: &mut Option<TreeEvents>,
 ) -> usize {
 	let oct = create_child(parent_ab, parent_loose, parent_layer, parent_id, child);
 	let oct_id = oct_slab.insert(oct);
 	let oct = unsafe { oct_slab.get_unchecked_mut(oct_id) };
-	if split_down(ab_map, adjust.1, deep, oct, oct_id, list) > 0 {
-		collect(oct_slab, ab_map, adjust, deep, oct_id);
+	if split_down(ab_map, adjust.1, deep, oct, oct_id, list, events) > 0 {
+		collect(oct_slab, ab_map, adjust, deep, oct_id, events);
 	}
 	oct_id
 }
@@ -1072,6 +2046,7 @@ fn split_down<S: BaseNum, T>(
 	parent: &mut OctNode<S>,
 	parent_id: usize,
 	list: &NodeList,
+	events: &mut Option<TreeEvents>,
 ) -> usize {
 	let two = S::one() + S::one();
 	let x1 = (parent.aabb.min.x + parent.aabb.max.x - parent.loose.x) / two;
@@ -1106,6 +2081,7 @@ fn split_down<S: BaseNum, T>(
 		let nid = id;
 		id = node.next;
 		node.prev = 0;
+		push_moved(events, nid);
 		if parent.layer >= node.layer {
 			node.parent = parent_id;
 			node.parent_child = 8;
@@ -1158,6 +2134,120 @@ fn split_down<S: BaseNum, T>(
 	}
 	parent.dirty
 }
+// 将ab节点加入列表的头部
+#[inline]
+fn push_list<S: BaseNum, T>(
+	ab_map: &mut VecMap<AbNode<S, T>>,
+	list: &mut NodeList,
+	id: usize,
+	parent: usize,
+	parent_child: usize,
+) {
+	let head = list.head;
+	{
+		let ab = unsafe { ab_map.get_unchecked_mut(id) };
+		ab.parent = parent;
+		ab.parent_child = parent_child;
+		ab.prev = 0;
+		ab.next = head;
+	}
+	if head > 0 {
+		let ab = unsafe { ab_map.get_unchecked_mut(head) };
+		ab.prev = id;
+	}
+	list.push(id);
+}
+// 将ab节点自顶向下分配到八叉节点上，数量超过阈值的子节点直接分化
+fn bulk_down<S: BaseNum, T>(
+	oct_slab: &mut Slab<OctNode<S>>,
+	ab_map: &mut VecMap<AbNode<S, T>>,
+	adjust: &(usize, usize),
+	deep: usize,
+	oct_id: usize,
+	ids: Vec<usize>,
+	events: &mut Option<TreeEvents>,
+) {
+	if ids.len() == 0 {
+		return;
+	}
+	let mut octs = Vec::new();
+	let mut splits = Vec::new();
+	{
+		let node = unsafe { oct_slab.get_unchecked_mut(oct_id) };
+		let abs = [
+			child_aabb(&node.aabb, &node.loose, 0),
+			child_aabb(&node.aabb, &node.loose, 1),
+			child_aabb(&node.aabb, &node.loose, 2),
+			child_aabb(&node.aabb, &node.loose, 3),
+			child_aabb(&node.aabb, &node.loose, 4),
+			child_aabb(&node.aabb, &node.loose, 5),
+			child_aabb(&node.aabb, &node.loose, 6),
+			child_aabb(&node.aabb, &node.loose, 7),
+		];
+		let mut childs: [Vec<usize>; 8] = Default::default();
+		for id in ids {
+			let child = {
+				let ab = unsafe { ab_map.get_unchecked(id) };
+				if node.layer >= ab.layer {
+					8
+				} else {
+					let mut i = 0;
+					while i < 8 && !abs[i].contains(&ab.aabb) {
+						i += 1;
+					}
+					i
+				}
+			};
+			if child < 8 {
+				childs[child].push(id);
+			} else {
+				push_list(ab_map, &mut node.nodes, id, oct_id, 8);
+			}
+		}
+		for i in 0..8 {
+			if childs[i].len() == 0 {
+				continue;
+			}
+			let ids = mem::replace(&mut childs[i], Vec::new());
+			match node.childs[i] {
+				ChildNode::Oct(oct, ref mut num) => {
+					*num += ids.len();
+					octs.push((oct, ids));
+				}
+				ChildNode::Ab(ref mut list) => {
+					if list.len + ids.len() > adjust.1 && node.layer < deep {
+						// 和原有的ab节点一起放到分化出来的八叉节点上
+						let mut ids = ids;
+						let mut id = list.head;
+						while id > 0 {
+							push_moved(events, id);
+							ids.push(id);
+							id = unsafe { ab_map.get_unchecked(id) }.next;
+						}
+						splits.push((i, ids));
+					} else {
+						for id in ids {
+							push_list(ab_map, list, id, oct_id, i);
+						}
+					}
+				}
+			}
+		}
+	}
+	for (i, ids) in splits {
+		let oct = {
+			let node = unsafe { oct_slab.get_unchecked(oct_id) };
+			create_child(&node.aabb, &node.loose, node.layer, oct_id, i)
+		};
+		let oct = oct_slab.insert(oct);
+		let node = unsafe { oct_slab.get_unchecked_mut(oct_id) };
+		node.childs[i] = ChildNode::Oct(oct, ids.len());
+		octs.push((oct, ids));
+	}
+	for (oct, ids) in octs {
+		bulk_down(oct_slab, ab_map, adjust, deep, oct, ids, events);
+	}
+}
 // 修复prev
 #[inline]
 fn fix_prev<S: BaseNum, T>(
@@ -1178,20 +2268,21 @@ fn fix_prev<S: BaseNum, T>(
 }
 
 // 查询空间内及相交的ab节点
-fn query<S: BaseNum, T, A, B>(
+fn query<S: BaseNum, T, F, G>(
 	oct_slab: &Slab<OctNode<S>>,
 	ab_map: &VecMap<AbNode<S, T>>,
 	oct_id: usize,
-	oct_arg: &A,
-	oct_func: fn(arg: &A, aabb: &Aabb3<S>) -> bool,
-	ab_arg: &mut B,
-	ab_func: fn(arg: &mut B, id: usize, aabb: &Aabb3<S>, bind: &T),
-) {
+	oct_func: &mut F,
+	ab_func: &mut G,
+) where
+	F: FnMut(&Aabb3<S>) -> bool,
+	G: FnMut(usize, &Aabb3<S>, &T),
+{
 	let node = unsafe { oct_slab.get_unchecked(oct_id) };
 	let mut id = node.nodes.head;
 	while id > 0 {
 		let ab = unsafe { ab_map.get_unchecked(id) };
-		ab_func(ab_arg, id, &ab.aabb, &ab.bind);
+		ab_func(id, &ab.aabb, &ab.bind);
 		id = ab.next;
 	}
 	#[macro_use()]
@@ -1199,16 +2290,16 @@ fn query<S: BaseNum, T, A, B>(
 		($a:ident, $i:tt) => {
 		match node.childs[$i] {
 			ChildNode::Oct(oct, ref num) if *num > 0 => {
-			if oct_func(oct_arg, &$a) {
-				query(oct_slab, ab_map, oct, oct_arg, oct_func, ab_arg, ab_func);
+			if oct_func(&$a) {
+				query(oct_slab, ab_map, oct, oct_func, ab_func);
 			}
 			}
 			ChildNode::Ab(ref list) if list.head > 0 => {
-			if oct_func(oct_arg, &$a) {
+			if oct_func(&$a) {
 				let mut id = list.head;
 				loop {
 				let ab = unsafe { ab_map.get_unchecked(id) };
-				ab_func(ab_arg, id, &ab.aabb, &ab.bind);
+				ab_func(id, &ab.aabb, &ab.bind);
 				id = ab.next;
 				if id == 0 {
 					break;
@@ -1263,37 +2354,381 @@ fn query<S: BaseNum, T, A, B>(
 	child_macro!(a, 7);
 }
 
-// 和指定的列表进行碰撞
-fn collision_list<S: BaseNum, T, A>(
+// 点到aabb距离的平方，点在aabb内为0
+#[inline]
+fn distance2<S: BaseFloat>(p: &Point3<S>, aabb: &Aabb3<S>) -> S {
+	let x = if p.x < aabb.min.x {
+		aabb.min.x - p.x
+	} else if p.x > aabb.max.x {
+		p.x - aabb.max.x
+	} else {
+		S::zero()
+	};
+	let y = if p.y < aabb.min.y {
+		aabb.min.y - p.y
+	} else if p.y > aabb.max.y {
+		p.y - aabb.max.y
+	} else {
+		S::zero()
+	};
+	let z = if p.z < aabb.min.z {
+		aabb.min.z - p.z
+	} else if p.z > aabb.max.z {
+		p.z - aabb.max.z
+	} else {
+		S::zero()
+	};
+	x * x + y * y + z * z
+}
+
+// 按距离优先遍历时的候选项
+#[derive(Debug, Clone, Copy)]
+enum NearKind {
+	Oct(usize),  // 八叉节点
+	List(usize), // 子八叉节点上的ab节点列表的头
+	Ab(usize),   // ab节点
+}
+// 按距离优先遍历时的队列元素，距离小的先出队
+#[derive(Debug, Clone, Copy)]
+struct NearItem<S> {
+	dist: S,
+	kind: NearKind,
+}
+impl<S: PartialOrd> PartialEq for NearItem<S> {
+	fn eq(&self, other: &Self) -> bool {
+		self.dist == other.dist
+	}
+}
+impl<S: PartialOrd> Eq for NearItem<S> {}
+impl<S: PartialOrd> PartialOrd for NearItem<S> {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+impl<S: PartialOrd> Ord for NearItem<S> {
+	fn cmp(&self, other: &Self) -> Ordering {
+		// BinaryHeap是大顶堆，反过来比较，让距离小的先出队
+		other.dist.partial_cmp(&self.dist).unwrap_or(Ordering::Equal)
+	}
+}
+
+// 将列表上的ab节点按距离放入队列
+#[inline]
+fn best_list<S: BaseFloat, T, F: Fn(&Aabb3<S>) -> Option<S>>(
+	ab_map: &VecMap<AbNode<S, T>>,
+	max: S,
+	mut id: usize,
+	heap: &mut BinaryHeap<NearItem<S>>,
+	func: &F,
+) {
+	while id > 0 {
+		let ab = unsafe { ab_map.get_unchecked(id) };
+		match func(&ab.aabb) {
+			Some(d) if d <= max => heap.push(NearItem { dist: d, kind: NearKind::Ab(id) }),
+			_ => (),
+		}
+		id = ab.next;
+	}
+}
+
+// 按八叉节点的松散包围盒的距离优先遍历，func计算aabb的距离，返回None表示不相交。
+// 按距离从近到远返回最多k个距离不超过max的ab节点
+fn best_first<'a, S: BaseFloat, T, F: Fn(&Aabb3<S>) -> Option<S>>(
+	oct_slab: &Slab<OctNode<S>>,
+	ab_map: &'a VecMap<AbNode<S, T>>,
+	outer: &NodeList,
+	k: usize,
+	max: S,
+	func: F,
+) -> Vec<(usize, S, &'a T)> {
+	let mut result = Vec::new();
+	if k == 0 {
+		return result;
+	}
+	let mut heap = BinaryHeap::new();
+	// 根节点的nodes上有超出根节点范围的ab节点，所以根节点的距离按0计算
+	heap.push(NearItem { dist: S::zero(), kind: NearKind::Oct(1) });
+	best_list(ab_map, max, outer.head, &mut heap, &func);
+	while let Some(item) = heap.pop() {
+		match item.kind {
+			NearKind::Ab(id) => {
+				let ab = unsafe { ab_map.get_unchecked(id) };
+				result.push((id, item.dist, &ab.bind));
+				if result.len() >= k {
+					break;
+				}
+			}
+			NearKind::List(head) => best_list(ab_map, max, head, &mut heap, &func),
+			NearKind::Oct(oct_id) => {
+				let node = unsafe { oct_slab.get_unchecked(oct_id) };
+				best_list(ab_map, max, node.nodes.head, &mut heap, &func);
+				for i in 0..8 {
+					let kind = match node.childs[i] {
+						ChildNode::Oct(oct, num) if num > 0 => NearKind::Oct(oct),
+						ChildNode::Ab(ref list) if list.head > 0 => NearKind::List(list.head),
+						_ => continue,
+					};
+					match func(&child_aabb(&node.aabb, &node.loose, i)) {
+						Some(d) if d <= max => heap.push(NearItem { dist: d, kind: kind }),
+						_ => (),
+					}
+				}
+			}
+		}
+	}
+	result
+}
+
+// 最近查询，max为距离平方的上限
+#[inline]
+fn nearest<'a, S: BaseFloat, T>(
+	oct_slab: &Slab<OctNode<S>>,
+	ab_map: &'a VecMap<AbNode<S, T>>,
+	outer: &NodeList,
+	point: &Point3<S>,
+	k: usize,
+	max: S,
+) -> Vec<(usize, S, &'a T)> {
+	let mut r = best_first(oct_slab, ab_map, outer, k, max, |aabb| Some(distance2(point, aabb)));
+	for e in r.iter_mut() {
+		e.1 = e.1.sqrt();
+	}
+	r
+}
+
+// 射线起点到aabb的距离，起点在aabb内为0，不相交返回None
+#[inline]
+fn ray_distance<S: BaseFloat>(ray: &Ray3<S>, aabb: &Aabb3<S>) -> Option<S> {
+	if aabb.contains(&ray.origin) {
+		return Some(S::zero());
+	}
+	match ray.intersection(aabb) {
+		Some(p) => Some((p - ray.origin).magnitude()),
+		_ => None,
+	}
+}
+
+// 遍历列表上的ab节点，relation为In表示列表在视锥体内，不需要检查
+#[inline]
+fn frustum_list<S: BaseFloat, T, F: FnMut(usize, &Aabb3<S>, &T)>(
+	ab_map: &VecMap<AbNode<S, T>>,
+	frustum: &Frustum<S>,
+	relation: Relation,
+	mut id: usize,
+	func: &mut F,
+) {
+	while id > 0 {
+		let ab = unsafe { ab_map.get_unchecked(id) };
+		if relation == Relation::In || frustum.contains(&ab.aabb) != Relation::Out {
+			func(id, &ab.aabb, &ab.bind);
+		}
+		id = ab.next;
+	}
+}
+
+// 视锥体查询，relation为In表示该八叉节点完全在视锥体内，其下所有的ab节点都不需要检查
+fn frustum_query<S: BaseFloat, T, F: FnMut(usize, &Aabb3<S>, &T)>(
+	oct_slab: &Slab<OctNode<S>>,
+	ab_map: &VecMap<AbNode<S, T>>,
+	oct_id: usize,
+	frustum: &Frustum<S>,
+	relation: Relation,
+	func: &mut F,
+) {
+	let node = unsafe { oct_slab.get_unchecked(oct_id) };
+	frustum_list(ab_map, frustum, relation, node.nodes.head, func);
+	for i in 0..8 {
+		match node.childs[i] {
+			ChildNode::Oct(oct, num) if num > 0 => {
+				let r = if relation == Relation::In {
+					Relation::In
+				} else {
+					frustum.contains(&child_aabb(&node.aabb, &node.loose, i))
+				};
+				if r != Relation::Out {
+					frustum_query(oct_slab, ab_map, oct, frustum, r, func);
+				}
+			}
+			ChildNode::Ab(ref list) if list.head > 0 => {
+				let r = if relation == Relation::In {
+					Relation::In
+				} else {
+					frustum.contains(&child_aabb(&node.aabb, &node.loose, i))
+				};
+				if r != Relation::Out {
+					frustum_list(ab_map, frustum, r, list.head, func);
+				}
+			}
+			_ => (),
+		}
+	}
+}
+
+// 细节层次查询的参数，都用平方比较，避免开方
+struct Lod<S: BaseFloat> {
+	eye: Point3<S>,
+	scale2: S,
+	min2: S,
+}
+impl<S: BaseFloat> Lod<S> {
+	// 大小为size的物体在aabb内时，投影大小是否可能不小于最小值
+	#[inline]
+	fn visible(&self, size2: S, aabb: &Aabb3<S>) -> bool {
+		size2 * self.scale2 >= self.min2 * distance2(&self.eye, aabb)
+	}
+}
+
+// 遍历列表上投影大小足够且和形状相交的ab节点
+#[inline]
+fn lod_list<S: BaseFloat, T, B: Discrete<Aabb3<S>>, F: FnMut(usize, &Aabb3<S>, &T)>(
+	ab_map: &VecMap<AbNode<S, T>>,
+	shape: &B,
+	lod: &Lod<S>,
+	mut id: usize,
+	func: &mut F,
+) {
+	while id > 0 {
+		let ab = unsafe { ab_map.get_unchecked(id) };
+		if lod.visible(ab.aabb.dim().magnitude2(), &ab.aabb) && shape.intersects(&ab.aabb) {
+			func(id, &ab.aabb, &ab.bind);
+		}
+		id = ab.next;
+	}
+}
+
+// 细节层次查询，子节点下的ab节点大小不超过本节点的松散值，投影大小不够则跳过整个子节点
+fn lod_query<S: BaseFloat, T, B: Discrete<Aabb3<S>>, F: FnMut(usize, &Aabb3<S>, &T)>(
+	oct_slab: &Slab<OctNode<S>>,
+	ab_map: &VecMap<AbNode<S, T>>,
+	oct_id: usize,
+	shape: &B,
+	lod: &Lod<S>,
+	func: &mut F,
+) {
+	let node = unsafe { oct_slab.get_unchecked(oct_id) };
+	lod_list(ab_map, shape, lod, node.nodes.head, func);
+	let size2 = node.loose.magnitude2();
+	for i in 0..8 {
+		let head = match node.childs[i] {
+			ChildNode::Oct(_, num) if num > 0 => 0,
+			ChildNode::Ab(ref list) if list.head > 0 => list.head,
+			_ => continue,
+		};
+		let a = child_aabb(&node.aabb, &node.loose, i);
+		if !lod.visible(size2, &a) || !shape.intersects(&a) {
+			continue;
+		}
+		match node.childs[i] {
+			ChildNode::Oct(oct, _) => lod_query(oct_slab, ab_map, oct, shape, lod, func),
+			_ => lod_list(ab_map, shape, lod, head, func),
+		}
+	}
+}
+
+// 和指定的列表进行碰撞，只检查id大于min且层不大于limit的ab节点，func返回false表示停止
+fn collision_list<S: BaseNum, T, F: FnMut(usize, &Aabb3<S>, &T, usize, &Aabb3<S>, &T) -> bool>(
 	map: &VecMap<AbNode<S, T>>,
 	id: usize,
 	aabb: &Aabb3<S>,
 	bind: &T,
-	arg: &mut A,
-	func: fn(arg: &mut A, a_id: usize, a_aabb: &Aabb3<S>, a_bind: &T, b_id: usize, b_aabb: &Aabb3<S>, b_bind: &T) -> bool,
+	limit: usize,
+	min: usize,
+	func: &mut F,
 	mut head: usize,
-) {
+) -> bool {
 	while head > 0 {
 		let b = unsafe { map.get_unchecked(head) };
-		func(arg, id, aabb, bind, head, &b.aabb, &b.bind);
+		if head != id && head > min && b.layer <= limit && intersects(aabb, &b.aabb) {
+			if !func(id, aabb, bind, head, &b.aabb, &b.bind) {
+				return false;
+			}
+		}
 		head = b.next;
 	}
+	true
 }
 
-// 和指定的节点进行碰撞
-// fn collision_node<S: BaseNum, T, A>(
-//   oct_slab: &Slab<OctNode<S>>,
-//   ab_map: &Slab<AbNode<S, T>>,
-//   id: usize,
-//   aabb: &Aabb3<S>,
-//   bind: &T,
-//   arg: &mut A,
-//   func: fn(arg: &mut A, a_id: usize, a_aabb: &Aabb3<S>, a_bind: &T, b_id: usize, b_aabb: &Aabb3<S>, b_bind: &T) -> bool,
-//   parent: usize,
-//   parent_child: usize,
-// ) {
+// 和指定的节点进行碰撞，检查该节点的nodes，及和aabb相交的子八叉节点
+fn collision_node<S: BaseNum, T, F: FnMut(usize, &Aabb3<S>, &T, usize, &Aabb3<S>, &T) -> bool>(
+	oct_slab: &Slab<OctNode<S>>,
+	ab_map: &VecMap<AbNode<S, T>>,
+	oct_id: usize,
+	id: usize,
+	aabb: &Aabb3<S>,
+	bind: &T,
+	limit: usize,
+	min: usize,
+	func: &mut F,
+) -> bool {
+	let node = unsafe { oct_slab.get_unchecked(oct_id) };
+	if !collision_list(ab_map, id, aabb, bind, limit, min, func, node.nodes.head) {
+		return false;
+	}
+	// 子八叉节点上的ab节点的层都大于本节点的层
+	if node.layer >= limit {
+		return true;
+	}
+	for i in 0..8 {
+		match node.childs[i] {
+			ChildNode::Oct(oct, num) if num > 0 => {
+				if intersects(aabb, &child_aabb(&node.aabb, &node.loose, i))
+					&& !collision_node(oct_slab, ab_map, oct, id, aabb, bind, limit, min, func) {
+					return false;
+				}
+			}
+			ChildNode::Ab(ref list) if list.head > 0 => {
+				if intersects(aabb, &child_aabb(&node.aabb, &node.loose, i))
+					&& !collision_list(ab_map, id, aabb, bind, limit, min, func, list.head) {
+					return false;
+				}
+			}
+			_ => (),
+		}
+	}
+	true
+}
 
-// }
+// 检查指定ab节点的碰撞对，从根节点向下检查重叠的松散区域。不被根节点包含的ab节点，还需要检查outer
+#[inline]
+fn collision_ab<S: BaseNum, T, F: FnMut(usize, &Aabb3<S>, &T, usize, &Aabb3<S>, &T) -> bool>(
+	oct_slab: &Slab<OctNode<S>>,
+	ab_map: &VecMap<AbNode<S, T>>,
+	outer: &NodeList,
+	id: usize,
+	a: &AbNode<S, T>,
+	limit: usize,
+	min: usize,
+	func: &mut F,
+) -> bool {
+	if !collision_node(oct_slab, ab_map, 1, id, &a.aabb, &a.bind, limit, min, func) {
+		return false;
+	}
+	let root = unsafe { oct_slab.get_unchecked(1) };
+	if root.aabb.contains(&a.aabb) {
+		return true;
+	}
+	collision_list(ab_map, id, &a.aabb, &a.bind, limit, min, func, outer.head)
+}
+
+// 列表上的每个ab节点，和id比自己大的ab节点检查碰撞对
+#[inline]
+fn collision_pairs_list<S: BaseNum, T, F: FnMut(usize, &Aabb3<S>, &T, usize, &Aabb3<S>, &T) -> bool>(
+	oct_slab: &Slab<OctNode<S>>,
+	ab_map: &VecMap<AbNode<S, T>>,
+	outer: &NodeList,
+	mut id: usize,
+	limit: usize,
+	func: &mut F,
+) -> bool {
+	while id > 0 {
+		let a = unsafe { ab_map.get_unchecked(id) };
+		if !collision_ab(oct_slab, ab_map, outer, id, a, limit, id, func) {
+			return false;
+		}
+		id = a.next;
+	}
+	true
+}
 
 
 #[test]
@@ -1716,3 +3151,801 @@ fn log(
 		println!("oct=========, id:{}, oct: {:?}", id, n);
 	}
 }
+
+#[cfg(test)]
+fn test_tree() -> (Tree<f32, usize>, Vec<(usize, Aabb3<f32>)>) {
+	let mut tree = Tree::new(Aabb3::new(Point3::new(0f32,0f32,0f32), Point3::new(1000f32,1000f32,1000f32)), 0, 0, 0, 0);
+	let mut list = Vec::new();
+	for i in 0..300 {
+		let x = ((i * 37) % 100) as f32 * 10.0;
+		let y = ((i * 53) % 100) as f32 * 10.0;
+		let z = ((i * 71) % 100) as f32 * 10.0;
+		let s = (i % 7) as f32 * 3.0 + 1.0;
+		let aabb = Aabb3::new(Point3::new(x, y, z), Point3::new(x + s, y + s, z + s));
+		tree.add(i + 1, aabb.clone(), i + 1);
+		list.push((i + 1, aabb));
+	}
+	// 和根节点相交及在根节点外的ab节点
+	let aabb = Aabb3::new(Point3::new(-10.0, 0.0, 0.0), Point3::new(10.0, 10.0, 10.0));
+	tree.add(301, aabb.clone(), 301);
+	list.push((301, aabb));
+	let aabb = Aabb3::new(Point3::new(-30.0, 0.0, 0.0), Point3::new(-20.0, 10.0, 10.0));
+	tree.add(302, aabb.clone(), 302);
+	list.push((302, aabb));
+	tree.collect();
+	(tree, list)
+}
+
+#[test]
+fn test_nearest(){
+	let (tree, list) = test_tree();
+	for p in [Point3::new(-40.0f32, 5.0, 5.0), Point3::new(500.0, 500.0, 500.0), Point3::new(1200.0, 30.0, 990.0)].iter() {
+		let mut all: Vec<f32> = list.iter().map(|&(_, ref aabb)| distance2(p, aabb).sqrt()).collect();
+		all.sort_by(|a, b| a.partial_cmp(b).unwrap());
+		let r = tree.query_nearest(p, 10);
+		assert_eq!(r.len(), 10);
+		for i in 0..10 {
+			assert_eq!(r[i].1, all[i]);
+			assert_eq!(*r[i].2, r[i].0);
+		}
+		let r = tree.query_nearest_within(p, 100.0, 0);
+		assert_eq!(r.len(), all.iter().filter(|d| **d <= 100.0).count());
+		for i in 0..r.len() {
+			assert_eq!(r[i].1, all[i]);
+		}
+		assert_eq!(tree.query_nearest_within(p, 100.0, 3).len(), r.len().min(3));
+	}
+}
+
+#[test]
+fn test_raycast(){
+	let (tree, list) = test_tree();
+	let rays = [
+		Ray3::new(Point3::new(-50.0f32, 5.0, 5.0), Vector3::new(1.0, 0.0, 0.0)),
+		Ray3::new(Point3::new(0.0f32, 0.0, 0.0), Vector3::new(1.0, 1.0, 1.0).normalize()),
+		Ray3::new(Point3::new(505.0f32, 2000.0, 505.0), Vector3::new(0.0, -1.0, 0.0)),
+	];
+	for ray in rays.iter() {
+		let mut all: Vec<f32> = list.iter().filter_map(|&(_, ref aabb)| ray_distance(ray, aabb)).filter(|d| *d <= 1500.0).collect();
+		all.sort_by(|a, b| a.partial_cmp(b).unwrap());
+		let r = tree.raycast(ray, 1500.0);
+		assert_eq!(r.len(), all.len());
+		for i in 0..r.len() {
+			assert_eq!(r[i].1, all[i]);
+		}
+		match tree.raycast_first(ray, 1500.0) {
+			Some(first) => assert_eq!(first.1, all[0]),
+			_ => assert_eq!(all.len(), 0),
+		}
+	}
+}
+
+#[test]
+fn test_frustum(){
+	use cgmath::{perspective, Deg, Matrix4};
+	let (tree, list) = test_tree();
+	let views = [
+		Matrix4::look_at(Point3::new(-100.0f32, 500.0, 500.0), Point3::new(500.0, 500.0, 500.0), Vector3::new(0.0, 1.0, 0.0)),
+		Matrix4::look_at(Point3::new(500.0f32, 500.0, 500.0), Point3::new(1000.0, 1000.0, 0.0), Vector3::new(0.0, 0.0, 1.0)),
+		Matrix4::look_at(Point3::new(5000.0f32, 5000.0, 5000.0), Point3::new(500.0, 500.0, 500.0), Vector3::new(0.0, 1.0, 0.0)),
+	];
+	for view in views.iter() {
+		let frustum = Frustum::from_matrix4(perspective(Deg(60.0f32), 1.5, 1.0, 1000.0) * view).unwrap();
+		let mut all: Vec<usize> = list.iter().filter(|&&(_, ref aabb)| frustum.contains(aabb) != Relation::Out).map(|&(id, _)| id).collect();
+		let mut r = Vec::new();
+		tree.query_frustum(&frustum, &mut r, |r: &mut Vec<usize>, id, _, _| r.push(id));
+		all.sort();
+		r.sort();
+		assert_eq!(r, all);
+	}
+}
+
+#[test]
+fn test_collision(){
+	let mut tree = Tree::new(Aabb3::new(Point3::new(0f32,0f32,0f32), Point3::new(1000f32,1000f32,1000f32)), 0, 0, 0, 0);
+	let mut list = Vec::new();
+	for i in 0..400 {
+		let x = ((i * 37) % 100) as f32 * 4.0;
+		let y = ((i * 53) % 100) as f32 * 4.0;
+		let z = ((i * 71) % 100) as f32 * 4.0;
+		let s = (i % 13) as f32 * 9.0 + 1.0;
+		let aabb = Aabb3::new(Point3::new(x, y, z), Point3::new(x + s, y + s, z + s));
+		tree.add(i + 1, aabb.clone(), i + 1);
+		list.push((i + 1, aabb));
+	}
+	let aabb = Aabb3::new(Point3::new(-10.0, 0.0, 0.0), Point3::new(10.0, 10.0, 10.0));
+	tree.add(401, aabb.clone(), 401);
+	list.push((401, aabb));
+	let aabb = Aabb3::new(Point3::new(-30.0, 0.0, 0.0), Point3::new(-5.0, 10.0, 10.0));
+	tree.add(402, aabb.clone(), 402);
+	list.push((402, aabb));
+	// 大的ab节点放在第0层
+	let aabb = Aabb3::new(Point3::new(100.0, 100.0, 100.0), Point3::new(700.0, 700.0, 700.0));
+	assert_eq!(tree.get_layer(&aabb), 0);
+	tree.add(403, aabb.clone(), 403);
+	list.push((403, aabb));
+	tree.collect();
+	let mut all = Vec::new();
+	for &(a, ref aa) in list.iter() {
+		for &(b, ref bb) in list.iter() {
+			if a < b && intersects(aa, bb) {
+				all.push((a, b));
+			}
+		}
+	}
+	let mut r = Vec::new();
+	tree.collision_pairs(&mut r, |r: &mut Vec<(usize, usize)>, a, _, _, b, _, _| {
+		r.push((a.min(b), a.max(b)));
+		true
+	});
+	let len = r.len();
+	r.sort();
+	r.dedup();
+	assert_eq!(r.len(), len);
+	assert_eq!(r, all);
+	assert!(all.len() > 0);
+
+	let mut layer0 = 0;
+	for &(id, _) in list.iter() {
+		let mut expect: Vec<usize> = all.iter().filter(|p| p.0 == id || p.1 == id).map(|p| if p.0 == id { p.1 } else { p.0 }).collect();
+		let mut r = Vec::new();
+		tree.collision(id, None, &mut r, |r: &mut Vec<usize>, _, _, _, b, _, _| {
+			r.push(b);
+			true
+		});
+		expect.sort();
+		r.sort();
+		assert_eq!(r, expect);
+		// 只检查层不大于4的ab节点
+		let mut r = Vec::new();
+		tree.collision(id, Some(4), &mut r, |r: &mut Vec<usize>, _, _, _, b, _, _| {
+			r.push(b);
+			true
+		});
+		r.sort();
+		expect.retain(|b| tree.get_layer(tree.get(*b).unwrap().0) <= 4);
+		assert_eq!(r, expect);
+		// 只检查第0层的ab节点
+		let mut r = Vec::new();
+		tree.collision_with(id, Some(0), |_, _, _, b, _, _| {
+			r.push(b);
+			true
+		});
+		r.sort();
+		expect.retain(|b| tree.get_layer(tree.get(*b).unwrap().0) == 0);
+		assert_eq!(r, expect);
+		layer0 += r.len();
+	}
+	assert!(layer0 > 0);
+	// 返回false停止检查
+	let mut count = 0;
+	tree.collision_pairs(&mut count, |c: &mut usize, _, _, _, _, _, _| {
+		*c += 1;
+		false
+	});
+	assert_eq!(count, 1);
+}
+
+#[test]
+fn test_query_with(){
+	let (tree, list) = test_tree();
+	let aabb = Aabb3::new(Point3::new(-15.0f32, 0.0, 0.0), Point3::new(400.0, 600.0, 500.0));
+	let mut all: Vec<usize> = list.iter().filter(|&&(_, ref b)| intersects(&aabb, b)).map(|&(id, _)| id).collect();
+	all.sort();
+	let mut r: Vec<usize> = tree.iter_intersecting(&aabb).map(|(id, _, bind)| {
+		assert_eq!(id, *bind);
+		id
+	}).collect();
+	r.sort();
+	assert_eq!(r, all);
+	// 闭包查询不包括outer上的ab节点
+	let mut r = Vec::new();
+	tree.query_with(|a| intersects(&aabb, a), |id, a, _| if intersects(&aabb, a) {
+		r.push(id);
+	});
+	tree.query_outer_with(|id, a, _| if intersects(&aabb, a) {
+		r.push(id);
+	});
+	r.sort();
+	assert_eq!(r, all);
+	let mut count = 0;
+	tree.collision_pairs_with(|_, _, _, _, _, _| {
+		count += 1;
+		true
+	});
+	let mut r = 0;
+	tree.collision_pairs(&mut r, |r: &mut usize, _, _, _, _, _, _| {
+		*r += 1;
+		true
+	});
+	assert_eq!(count, r);
+}
+
+#[cfg(test)]
+fn query_ids(tree: &Tree<f32, usize>, aabb: &Aabb3<f32>) -> Vec<usize> {
+	let mut r: Vec<usize> = tree.iter_intersecting(aabb).map(|(id, _, _)| id).collect();
+	r.sort();
+	r
+}
+
+#[test]
+fn test_update_split() {
+	// update移动到兄弟节点的ab列表，列表超过adjust时也要设脏，collect时分化为子八叉节点
+	let mut tree = Tree::new(Aabb3::new(Point3::new(0f32,0f32,0f32), Point3::new(1024f32,1024f32,1024f32)), 0, 0, 0, 0);
+	tree.add(1, Aabb3::new(Point3::new(900.0, 900.0, 900.0), Point3::new(901.0, 901.0, 901.0)), 1);
+	for i in 2..7 {
+		let x = i as f32 * 2.0;
+		tree.add(i, Aabb3::new(Point3::new(x, x, x), Point3::new(x + 1.0, x + 1.0, x + 1.0)), i);
+	}
+	tree.collect();
+	let child = tree.ab_map.get(1).unwrap().parent_child;
+	match unsafe { tree.oct_slab.get_unchecked(1) }.childs[child] {
+		ChildNode::Ab(ref list) => assert_eq!(list.len, 1),
+		_ => panic!("invalid state"),
+	}
+	for i in 2..7 {
+		let x = 900.0 + i as f32 * 2.0;
+		tree.update(i, Aabb3::new(Point3::new(x, x, x), Point3::new(x + 1.0, x + 1.0, x + 1.0)));
+	}
+	tree.collect();
+	match unsafe { tree.oct_slab.get_unchecked(1) }.childs[child] {
+		ChildNode::Oct(_, num) => assert_eq!(num, 6),
+		_ => panic!("child list not split"),
+	}
+	assert_eq!(tree.validate(), Ok(()));
+}
+
+#[test]
+fn test_reclaim() {
+	// 反复在同一角落填满又清空，空的子八叉节点(包括其下的子孙节点)都要被销毁，八叉节点数量不能累积
+	let mut tree = Tree::new(Aabb3::new(Point3::new(0f32,0f32,0f32), Point3::new(1024f32,1024f32,1024f32)), 0, 0, 0, 0);
+	let mut full = 0;
+	for round in 0..4 {
+		for i in 0..200 {
+			let x = (i % 7) as f32 * 0.5;
+			let y = (i % 11) as f32 * 0.5;
+			let z = (i % 13) as f32 * 0.5;
+			tree.add(i + 1, Aabb3::new(Point3::new(x, y, z), Point3::new(x + 0.1, y + 0.1, z + 0.1)), i + 1);
+		}
+		tree.collect();
+		assert!(tree.oct_slab.len() > 3);
+		if round == 0 {
+			full = tree.oct_slab.len();
+		} else {
+			assert_eq!(tree.oct_slab.len(), full);
+		}
+		// 先清空一部分，使部分子八叉节点的数量为0
+		for i in 0..100 {
+			tree.remove(i + 1);
+		}
+		tree.collect();
+		assert!(tree.oct_slab.len() <= full);
+		for i in 100..200 {
+			tree.remove(i + 1);
+		}
+		tree.collect();
+		assert_eq!(tree.oct_slab.len(), 1);
+	}
+}
+
+#[test]
+fn test_save_load() {
+	let (mut tree, _) = test_tree();
+	// 留下未整理的脏节点
+	for i in 0..100 {
+		tree.shift(i * 3 + 1, Vector3::new(((i * 13) % 41) as f32 - 20.0, 5.0, -7.0));
+	}
+	for i in 0..20 {
+		tree.remove(i * 5 + 2);
+	}
+	let mut tree2 = Tree::load(tree.save()).unwrap();
+	assert_eq!(check_tree(&tree2.oct_slab, &tree2.ab_map, clone_tree(&tree2.oct_slab, &tree2.ab_map), 0), false);
+	assert_eq!(tree2.oct_slab.len(), tree.oct_slab.len());
+	assert_eq!(tree2.dirty.1, tree.dirty.1);
+	let aabbs = [
+		Aabb3::new(Point3::new(-50.0f32, -50.0, -50.0), Point3::new(1050.0, 1050.0, 1050.0)),
+		Aabb3::new(Point3::new(100.0f32, 200.0, 300.0), Point3::new(400.0, 500.0, 600.0)),
+	];
+	for aabb in aabbs.iter() {
+		assert_eq!(query_ids(&tree2, aabb), query_ids(&tree, aabb));
+	}
+	tree.collect();
+	tree2.collect();
+	assert_eq!(check_tree(&tree2.oct_slab, &tree2.ab_map, clone_tree(&tree2.oct_slab, &tree2.ab_map), 0), false);
+	assert_eq!(tree2.oct_slab.len(), tree.oct_slab.len());
+	for aabb in aabbs.iter() {
+		assert_eq!(query_ids(&tree2, aabb), query_ids(&tree, aabb));
+	}
+	// 恢复后的树可以继续修改
+	for i in 0..100 {
+		tree2.remove(i * 3 + 1);
+		tree.remove(i * 3 + 1);
+	}
+	tree.collect();
+	tree2.collect();
+	assert_eq!(tree2.oct_slab.len(), tree.oct_slab.len());
+	for aabb in aabbs.iter() {
+		assert_eq!(query_ids(&tree2, aabb), query_ids(&tree, aabb));
+	}
+}
+
+#[test]
+fn test_load_invalid() {
+	let (tree, _) = test_tree();
+	assert!(Tree::load(tree.save()).is_ok());
+	// 没有根节点
+	let mut data = tree.save();
+	data.octs.clear();
+	assert!(Tree::load(data).is_err());
+	// 子八叉节点的id不存在
+	let mut data = tree.save();
+	let i = (0..8).find(|i| match data.octs[0].childs[*i] {
+		ChildNode::Oct(_, _) => true,
+		_ => false,
+	}).unwrap();
+	let len = data.octs.len();
+	if let ChildNode::Oct(ref mut oct, _) = data.octs[0].childs[i] {
+		*oct = len + 1;
+	}
+	assert!(Tree::load(data).is_err());
+	// 父节点的id不存在
+	let mut data = tree.save();
+	data.octs[1].parent = 1000;
+	assert!(Tree::load(data).is_err());
+	// ab节点的链接错误
+	let mut data = tree.save();
+	data.abs[0].1.next = 100000;
+	assert!(Tree::load(data).is_err());
+	// 列表长度错误
+	let mut data = tree.save();
+	data.outer.len += 1;
+	assert!(Tree::load(data).is_err());
+	// 重复的ab节点
+	let mut data = tree.save();
+	let ab = data.abs[0].clone();
+	data.abs.push(ab);
+	assert!(Tree::load(data).is_err());
+	// 脏节点不存在
+	let mut data = tree.save();
+	data.dirty = vec![vec![1000]];
+	assert!(Tree::load(data).is_err());
+}
+
+#[cfg(all(test, feature = "serialize"))]
+extern crate serde_json;
+#[cfg(feature = "serialize")]
+#[test]
+fn test_serde() {
+	let (tree, _) = test_tree();
+	let json = serde_json::to_string(&tree.save()).unwrap();
+	let tree2: Tree<f32, usize> = Tree::load(serde_json::from_str(&json).unwrap()).unwrap();
+	let aabb = Aabb3::new(Point3::new(100.0f32, 200.0, 300.0), Point3::new(700.0, 800.0, 900.0));
+	assert_eq!(query_ids(&tree2, &aabb), query_ids(&tree, &aabb));
+	assert_eq!(tree2.get(301).map(|(_, b)| *b), Some(301));
+}
+
+#[test]
+fn test_validate() {
+	let (mut tree, list) = test_tree();
+	assert_eq!(tree.validate(), Ok(()));
+	let stats = tree.stats();
+	assert_eq!(stats.oct_count, tree.oct_slab.len());
+	assert_eq!(stats.octs_per_layer.iter().sum::<usize>(), stats.oct_count);
+	assert_eq!(stats.abs_per_layer.iter().sum::<usize>() + stats.outer, list.len());
+	assert_eq!(stats.outer, 1);
+	assert_eq!(stats.dirty, 0);
+	assert!(stats.octs_per_layer[stats.max_layer] > 0);
+	for i in 0..300 {
+		let d = ((i * 17) % 61) as f32 - 30.0;
+		tree.shift(i + 1, Vector3::new(d, -d, d * 0.5));
+		if i % 3 == 0 {
+			tree.remove(i + 1);
+		}
+		if i % 50 == 0 {
+			assert_eq!(tree.validate(), Ok(()));
+			tree.collect();
+			assert_eq!(tree.validate(), Ok(()));
+		}
+	}
+	for i in 0..300 {
+		tree.remove(i + 1);
+	}
+	tree.collect();
+	assert_eq!(tree.validate(), Ok(()));
+	assert_eq!(tree.stats().oct_count, 1);
+	// 破坏链表后应检查出错误
+	let (mut tree, _) = test_tree();
+	unsafe { tree.ab_map.get_unchecked_mut(1) }.prev = 2;
+	assert!(tree.validate().is_err());
+	// 孙八叉节点的id不存在，统计数量时返回错误
+	let (mut tree, _) = test_tree();
+	let id = tree.oct_slab.iter().map(|(id, _)| id).find(|id| *id > 1).unwrap();
+	unsafe { tree.oct_slab.get_unchecked_mut(id) }.childs[0] = ChildNode::Oct(100000, 0);
+	assert_eq!(tree.validate(), Err(format!("oct {}: invalid child oct 100000", id)));
+}
+
+#[test]
+fn test_grow() {
+	let mut tree = Tree::new(Aabb3::new(Point3::new(0f32,0f32,0f32), Point3::new(100f32,100f32,100f32)), 0, 0, 0, 0);
+	tree.set_grow(8);
+	let mut list = Vec::new();
+	for i in 0..200 {
+		let x = ((i * 37) % 100) as f32;
+		let y = ((i * 53) % 100) as f32;
+		let z = ((i * 71) % 100) as f32;
+		// 后一半的ab节点在根节点外
+		let o = if i < 100 { 0.0 } else { 700.0 };
+		let aabb = Aabb3::new(Point3::new(x + o, y - o, z), Point3::new(x + o + 2.0, y - o + 2.0, z + 2.0));
+		tree.add(i + 1, aabb.clone(), i + 1);
+		list.push((i + 1, aabb));
+	}
+	assert_eq!(tree.stats().outer, 100);
+	tree.collect();
+	assert_eq!(tree.validate(), Ok(()));
+	assert_eq!(tree.stats().outer, 0);
+	let root = tree.get_root().clone();
+	assert_eq!(root.dim().x, 1600.0);
+	assert!(root.min.y < -600.0 && root.max.z == 1600.0);
+	for &(id, ref aabb) in list.iter() {
+		assert_eq!(tree.get(id), Some((aabb, &id)));
+	}
+	let aabb = Aabb3::new(Point3::new(-50.0f32, -800.0, -50.0), Point3::new(750.0, 50.0, 50.0));
+	let all: Vec<usize> = list.iter().filter(|&&(_, ref a)| intersects(&aabb, a)).map(|&(id, _)| id).collect();
+	assert_eq!(query_ids(&tree, &aabb), all);
+	// 缩小根节点，超出的ab节点放到outer上
+	tree.set_grow(0);
+	tree.set_root(Aabb3::new(Point3::new(0f32,0f32,0f32), Point3::new(100f32,100f32,100f32)));
+	assert_eq!(tree.validate(), Ok(()));
+	assert_eq!(tree.stats().outer, 100);
+	assert_eq!(query_ids(&tree, &aabb), all);
+}
+
+#[test]
+fn test_grow_flat() {
+	// 平面的根节点，z轴长度为0，有高度或在平面外的ab节点也要能扩大根节点
+	let mut tree = Tree::new(Aabb3::new(Point3::new(0f32,0f32,0f32), Point3::new(100f32,100f32,0f32)), 0, 0, 0, 0);
+	tree.set_grow(1);
+	tree.add(1, Aabb3::new(Point3::new(10.0, 10.0, 0.0), Point3::new(12.0, 12.0, 0.0)), 1);
+	tree.add(2, Aabb3::new(Point3::new(150.0, 10.0, 0.0), Point3::new(152.0, 12.0, 3.0)), 2);
+	tree.add(3, Aabb3::new(Point3::new(20.0, 30.0, -5.0), Point3::new(22.0, 32.0, -5.0)), 3);
+	assert_eq!(tree.stats().outer, 2);
+	tree.collect();
+	assert_eq!(tree.stats().outer, 0);
+	assert_eq!(tree.validate(), Ok(()));
+	let root = tree.get_root().clone();
+	assert!(root.min.x == 0.0 && root.max.x >= 152.0);
+	assert!(root.min.z <= -5.0 && root.max.z >= 3.0);
+	assert_eq!(query_ids(&tree, &Aabb3::new(Point3::new(140.0, 0.0, -10.0), Point3::new(160.0, 20.0, 10.0))), vec![2]);
+}
+
+#[test]
+fn test_grow_fail() {
+	let root = Aabb3::new(Point3::new(0f32,0f32,0f32), Point3::new(100f32,100f32,100f32));
+	let mut tree = Tree::new(root.clone(), 0, 0, 0, 0);
+	tree.set_grow(2);
+	for i in 0..50 {
+		let x = (i * 2) as f32;
+		tree.add(i + 1, Aabb3::new(Point3::new(x, x, x), Point3::new(x + 1.0, x + 1.0, x + 1.0)), i + 1);
+	}
+	// 翻倍GROW_MAX次也包含不了的ab节点，不扩大也不重建
+	for i in 50..53 {
+		let x = 1.0e12 * (i - 49) as f32;
+		tree.add(i + 1, Aabb3::new(Point3::new(x, x, x), Point3::new(x + 1.0, x + 1.0, x + 1.0)), i + 1);
+	}
+	tree.collect();
+	tree.set_events(true);
+	for _ in 0..3 {
+		tree.collect();
+		assert_eq!(tree.get_root(), &root);
+		assert!(tree.take_events().moved.is_empty());
+	}
+	assert_eq!(tree.stats().outer, 3);
+	assert_eq!(tree.validate(), Ok(()));
+	// outer变化后重新尝试，能包含时扩大
+	for i in 50..53 {
+		tree.remove(i + 1);
+	}
+	for i in 50..53 {
+		let x = 150.0 + i as f32;
+		tree.add(i + 1, Aabb3::new(Point3::new(x, x, x), Point3::new(x + 1.0, x + 1.0, x + 1.0)), i + 1);
+	}
+	tree.collect();
+	assert_eq!(tree.get_root().max, Point3::new(400.0, 400.0, 400.0));
+	assert_eq!(tree.stats().outer, 0);
+	assert_eq!(tree.validate(), Ok(()));
+}
+
+#[test]
+fn test_grow_deep() {
+	// 整数空间的最大深度受根节点大小限制，根节点扩大后应恢复到指定的深度
+	let mut tree: Tree<i32, usize> = Tree::new(Aabb3::new(Point3::new(0, 0, 0), Point3::new(64, 64, 64)), 0, 0, 0, 10);
+	assert_eq!(tree.deep, 4);
+	tree.set_root(Aabb3::new(Point3::new(0, 0, 0), Point3::new(4096, 4096, 4096)));
+	assert_eq!(tree.deep, 10);
+	tree.set_root(Aabb3::new(Point3::new(0, 0, 0), Point3::new(64, 64, 64)));
+	assert_eq!(tree.deep, 4);
+	tree.set_grow(1);
+	for i in 0..3 {
+		let x = 1000 + i * 10;
+		tree.add(i as usize + 1, Aabb3::new(Point3::new(x, x, x), Point3::new(x + 1, x + 1, x + 1)), i as usize + 1);
+	}
+	tree.collect();
+	assert_eq!(tree.get_root().max, Point3::new(1024, 1024, 1024));
+	assert_eq!(tree.deep, 8);
+	assert_eq!(tree.validate(), Ok(()));
+	// 快照保留指定的深度
+	let mut tree2 = Tree::load(tree.save()).unwrap();
+	tree2.set_root(Aabb3::new(Point3::new(0, 0, 0), Point3::new(4096, 4096, 4096)));
+	assert_eq!(tree2.deep, 10);
+}
+
+#[test]
+fn test_bulk_add() {
+	let (tree, list) = test_tree();
+	let mut tree2 = Tree::new(Aabb3::new(Point3::new(0f32,0f32,0f32), Point3::new(1000f32,1000f32,1000f32)), 0, 0, 0, 0);
+	tree2.bulk_add(list.iter().map(|&(id, ref aabb)| (id, aabb.clone(), id)));
+	assert_eq!(tree2.validate(), Ok(()));
+	let (s1, s2) = (tree.stats(), tree2.stats());
+	assert_eq!(s2.octs_per_layer, s1.octs_per_layer);
+	assert_eq!(s2.abs_per_layer, s1.abs_per_layer);
+	assert_eq!(s2.outer, s1.outer);
+	assert_eq!(s2.dirty, 0);
+	let aabb = Aabb3::new(Point3::new(100.0f32, 200.0, 300.0), Point3::new(700.0, 800.0, 900.0));
+	assert_eq!(query_ids(&tree2, &aabb), query_ids(&tree, &aabb));
+	// 在已分化的树上批量添加
+	let (mut tree, _) = test_tree();
+	let mut list2 = Vec::new();
+	for i in 0..500 {
+		let x = ((i * 31) % 97) as f32 * 10.0;
+		let y = ((i * 43) % 97) as f32 * 10.0;
+		let z = ((i * 59) % 97) as f32 * 10.0;
+		let s = (i % 5) as f32 * 4.0 + 1.0;
+		list2.push((i + 1000, Aabb3::new(Point3::new(x, y, z), Point3::new(x + s, y + s, z + s)), i + 1000));
+	}
+	tree2.bulk_add(list2.clone());
+	for (id, aabb, bind) in list2 {
+		tree.add(id, aabb, bind);
+	}
+	tree.collect();
+	assert_eq!(tree2.validate(), Ok(()));
+	let (s1, s2) = (tree.stats(), tree2.stats());
+	assert_eq!(s2.octs_per_layer, s1.octs_per_layer);
+	assert_eq!(s2.abs_per_layer, s1.abs_per_layer);
+	assert_eq!(query_ids(&tree2, &aabb), query_ids(&tree, &aabb));
+}
+
+#[test]
+fn test_query_shape() {
+	use cgmath::{perspective, Deg, Matrix4};
+	use collision::Sphere;
+	let (tree, list) = test_tree();
+	let spheres = [
+		Sphere { center: Point3::new(500.0f32, 500.0, 500.0), radius: 200.0 },
+		Sphere { center: Point3::new(-20.0f32, 5.0, 5.0), radius: 12.0 },
+		Sphere { center: Point3::new(0.0f32, 1000.0, 0.0), radius: 350.0 },
+	];
+	for sphere in spheres.iter() {
+		let all: Vec<usize> = list.iter().filter(|&&(_, ref aabb)| sphere.intersects(aabb)).map(|&(id, _)| id).collect();
+		let mut r = Vec::new();
+		tree.query_shape(sphere, &mut r, |r: &mut Vec<usize>, id, _, _| r.push(id));
+		r.sort();
+		assert_eq!(r, all);
+	}
+	let view = Matrix4::look_at(Point3::new(-100.0f32, 500.0, 500.0), Point3::new(500.0, 500.0, 500.0), Vector3::new(0.0, 1.0, 0.0));
+	let frustum = Frustum::from_matrix4(perspective(Deg(60.0f32), 1.5, 1.0, 1000.0) * view).unwrap();
+	let mut all = Vec::new();
+	tree.query_frustum_with(&frustum, |id, _, _| all.push(id));
+	let mut r = Vec::new();
+	tree.query_shape_with(&frustum, |id, _, _| r.push(id));
+	all.sort();
+	r.sort();
+	assert_eq!(r, all);
+}
+
+#[test]
+fn test_events() {
+	let mut tree = Tree::new(Aabb3::new(Point3::new(0f32,0f32,0f32), Point3::new(1000f32,1000f32,1000f32)), 0, 0, 0, 0);
+	tree.add(1, Aabb3::new(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0)), 1);
+	assert_eq!(tree.take_events().added.len(), 0);
+	tree.set_events(true);
+	for i in 2..10 {
+		let x = i as f32 * 10.0;
+		tree.add(i, Aabb3::new(Point3::new(x, x, x), Point3::new(x + 1.0, x + 1.0, x + 1.0)), i);
+	}
+	let e = tree.take_events();
+	assert_eq!(e.added, (2..10).collect::<Vec<usize>>());
+	assert_eq!(e.moved.len(), 0);
+	// 整理时分化，子八叉节点上的ab节点都被移动
+	tree.collect();
+	let e = tree.take_events();
+	assert!(e.moved.len() > 5);
+	assert_eq!(e.added.len() + e.removed.len(), 0);
+	// 在同一子八叉节点内移动不记录
+	tree.shift(5, Vector3::new(0.5, 0.5, 0.5));
+	assert_eq!(tree.take_events().moved.len(), 0);
+	tree.shift(5, Vector3::new(800.0, 800.0, 800.0));
+	tree.shift(5, Vector3::new(1.0, 0.0, 0.0));
+	tree.remove(6);
+	let e = tree.take_events();
+	assert_eq!(e.moved, vec![5]);
+	assert_eq!(e.removed, vec![6]);
+	tree.set_events(false);
+	tree.remove(7);
+	assert_eq!(tree.take_events().removed.len(), 0);
+}
+
+#[test]
+fn test_pair_diff() {
+	let mut tree = Tree::new(Aabb3::new(Point3::new(0f32,0f32,0f32), Point3::new(1000f32,1000f32,1000f32)), 0, 0, 0, 0);
+	for i in 1..20 {
+		let x = i as f32 * 20.0;
+		tree.add(i, Aabb3::new(Point3::new(x, 0.0, 0.0), Point3::new(x + 10.0, 10.0, 10.0)), i);
+	}
+	tree.add(20, Aabb3::new(Point3::new(15.0, 0.0, 0.0), Point3::new(25.0, 10.0, 10.0)), 20);
+	tree.collect();
+	let mut diff = PairDiff::new();
+	assert_eq!(diff.diff(&tree), (vec![(1, 20)], vec![]));
+	assert_eq!(diff.diff(&tree), (vec![], vec![]));
+	tree.shift(20, Vector3::new(20.0, 0.0, 0.0));
+	tree.collect();
+	assert_eq!(diff.diff(&tree), (vec![(2, 20)], vec![(1, 20)]));
+	tree.remove(2);
+	assert_eq!(diff.diff(&tree), (vec![], vec![(2, 20)]));
+	assert_eq!(diff.pairs().len(), 0);
+}
+
+// 通过SpatialIndex接口操作索引，返回查询到的绑定值
+#[cfg(test)]
+fn index_results<I: SpatialIndex<f32, Value = usize>>(index: &mut I, list: &Vec<(usize, Aabb3<f32>)>) -> Vec<Vec<usize>> {
+	let handles: Vec<usize> = list.iter().map(|&(id, ref aabb)| index.insert(aabb.clone(), id)).collect();
+	index.maintain();
+	for i in 0..list.len() / 4 {
+		let aabb = &list[i].1;
+		let d = Vector3::new(15.0, 0.0, 5.0);
+		assert!(index.update(handles[i], Aabb3::new(aabb.min + d, aabb.max + d)));
+	}
+	for i in (list.len() / 2..list.len()).filter(|i| i % 5 == 0) {
+		assert_eq!(index.remove(handles[i]), Some(list[i].0));
+		assert_eq!(index.remove(handles[i]), None);
+	}
+	index.maintain();
+	let mut r = vec![vec![index.len()]];
+	for &(min, max) in [(0.0, 1000.0), (100.0, 300.0), (-50.0, 20.0), (480.0, 520.0)].iter() {
+		let mut ids = Vec::new();
+		index.query_aabb(&Aabb3::new(Point3::new(min, min, min), Point3::new(max, max, max)), |_, _, &v| ids.push(v));
+		ids.sort();
+		r.push(ids);
+	}
+	let ray = Ray3::new(Point3::new(-100.0, 5.0, 5.0), Vector3::new(1.0, 0.0, 0.0));
+	let hits = index.query_ray(&ray, 2000.0);
+	for i in 1..hits.len() {
+		assert!(hits[i - 1].1 <= hits[i].1);
+	}
+	let mut ids: Vec<usize> = hits.iter().map(|&(h, _)| *index.get(h).unwrap().1).collect();
+	ids.sort();
+	r.push(ids);
+	let mut pairs = Vec::new();
+	index.pairs(|a, b| {
+		let (a, b) = (*index.get(a).unwrap().1, *index.get(b).unwrap().1);
+		pairs.push(if a < b { a * 1000 + b } else { b * 1000 + a });
+	});
+	pairs.sort();
+	r.push(pairs);
+	r
+}
+
+#[test]
+fn test_spatial_index() {
+	use collision::dbvt::{DynamicBoundingVolumeTree, TreeValueWrapped};
+	let (_, list) = test_tree();
+	let mut tree = Tree::new(Aabb3::new(Point3::new(0f32,0f32,0f32), Point3::new(1000f32,1000f32,1000f32)), 0, 0, 0, 0);
+	let mut dbvt = DynamicBoundingVolumeTree::<TreeValueWrapped<usize, Aabb3<f32>>>::new();
+	let r = index_results(&mut tree, &list);
+	assert!(r[5].len() > 0);
+	assert_eq!(r, index_results(&mut dbvt, &list));
+	assert!(tree.validate().is_ok());
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_par_query() {
+	fn assert_sync<T: Sync>(_: &T) {}
+	let (tree, _) = test_tree();
+	assert_sync(&tree);
+	let mut aabbs = Vec::new();
+	let mut rays = Vec::new();
+	for i in 0..100 {
+		let x = ((i * 41) % 100) as f32 * 10.0 - 20.0;
+		let y = ((i * 29) % 100) as f32 * 10.0;
+		aabbs.push(Aabb3::new(Point3::new(x, y, 0.0), Point3::new(x + 80.0, y + 80.0, 1000.0)));
+		rays.push(Ray3::new(Point3::new(x, y, -100.0), Vector3::new(0.1, 0.1, 1.0).normalize()));
+	}
+	let r = tree.par_query(&aabbs);
+	assert_eq!(r.len(), aabbs.len());
+	for (aabb, list) in aabbs.iter().zip(r.iter()) {
+		let mut ids: Vec<usize> = list.iter().map(|&(id, _)| id).collect();
+		ids.sort();
+		assert_eq!(ids, query_ids(&tree, aabb));
+	}
+	let r = tree.par_query_shape(&aabbs);
+	for (aabb, list) in aabbs.iter().zip(r.iter()) {
+		let mut ids: Vec<usize> = list.iter().map(|&(id, _)| id).collect();
+		ids.sort();
+		assert_eq!(ids, query_ids(&tree, aabb));
+	}
+	let r = tree.par_raycast(&rays, 2000.0);
+	for (ray, list) in rays.iter().zip(r.iter()) {
+		assert_eq!(list, &tree.raycast(ray, 2000.0));
+	}
+	assert!(r.iter().any(|list| list.len() > 0));
+}
+
+#[test]
+fn test_handle() {
+	let mut tree = HandleTree::new(Aabb3::new(Point3::new(0f32,0f32,0f32), Point3::new(1000f32,1000f32,1000f32)), 0, 0, 0, 0);
+	let mut handles = Vec::new();
+	for i in 0..20 {
+		let x = i as f32 * 40.0;
+		handles.push(tree.add(Aabb3::new(Point3::new(x, x, x), Point3::new(x + 10.0, x + 10.0, x + 10.0)), i));
+	}
+	tree.collect();
+	assert_eq!(handles[5].index(), 6);
+	assert_eq!(tree.get(handles[5]).map(|(_, &b)| b), Some(5));
+	assert_eq!(tree.remove(handles[5]).map(|(_, b)| b), Some(5));
+	// 旧句柄失效
+	assert!(tree.remove(handles[5]).is_none());
+	let h = tree.add(Aabb3::new(Point3::new(1.0, 1.0, 1.0), Point3::new(2.0, 2.0, 2.0)), 100);
+	assert_eq!(h.index(), handles[5].index());
+	assert_eq!(h.generation(), handles[5].generation() + 1);
+	assert!(tree.get(handles[5]).is_none());
+	assert!(!tree.update(handles[5], Aabb3::new(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0))));
+	assert!(!tree.shift(handles[5], Vector3::new(1.0, 1.0, 1.0)));
+	assert!(!tree.update_bind(handles[5], 7));
+	assert_eq!(tree.get(h).map(|(_, &b)| b), Some(100));
+	assert!(tree.shift(h, Vector3::new(500.0, 500.0, 500.0)));
+	assert!(tree.update_bind(h, 101));
+	assert!(tree.remove(Handle::default()).is_none());
+	// 查询结果的id转为句柄
+	let mut r = Vec::new();
+	for (id, _, _) in tree.iter_intersecting(&Aabb3::new(Point3::new(490.0, 490.0, 490.0), Point3::new(510.0, 510.0, 510.0))) {
+		r.push(tree.handle(id).unwrap());
+	}
+	assert_eq!(r, vec![h]);
+	// 存储保持紧凑
+	for h in handles.iter() {
+		tree.remove(*h);
+	}
+	tree.collect();
+	assert_eq!(tree.stats().ab_count, 1);
+	let h2 = tree.add(Aabb3::new(Point3::new(1.0, 1.0, 1.0), Point3::new(2.0, 2.0, 2.0)), 200);
+	assert!(h2.index() <= 20);
+	assert!(tree.validate().is_ok());
+}
+
+#[test]
+fn test_query_lod() {
+	use cgmath::{perspective, Deg, Matrix4};
+	let (tree, list) = test_tree();
+	let proj = PerspectiveFov { fovy: Rad::from(Deg(60.0f32)), aspect: 1.5, near: 1.0, far: 2000.0 };
+	let scale = lod_scale(&proj, 720.0);
+	assert!((scale - 360.0 / (30.0f32).to_radians().tan()).abs() < 0.01);
+	let eye = Point3::new(-100.0f32, 500.0, 500.0);
+	let view = Matrix4::look_at(eye, Point3::new(500.0, 500.0, 500.0), Vector3::new(0.0, 1.0, 0.0));
+	let frustum = Frustum::from_matrix4(perspective(Deg(60.0f32), 1.5, 1.0, 2000.0) * view).unwrap();
+	let mut last = usize::max_value();
+	for &min_size in [0.0f32, 5.0, 10.0, 20.0, 40.0].iter() {
+		// 暴力计算
+		let mut all: Vec<usize> = list.iter().filter(|&&(_, ref aabb)| {
+			let size = aabb.dim().magnitude() * scale;
+			size * size >= min_size * min_size * distance2(&eye, aabb) && frustum.intersects(aabb)
+		}).map(|&(id, _)| id).collect();
+		all.sort();
+		let mut r = Vec::new();
+		tree.query_lod(&frustum, &eye, scale, min_size, &mut r, |r: &mut Vec<usize>, id, _, _| r.push(id));
+		r.sort();
+		assert_eq!(r, all);
+		assert!(r.len() <= last);
+		last = r.len();
+	}
+	assert!(last < list.len() / 2);
+}
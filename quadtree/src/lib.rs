@@ -0,0 +1,1089 @@
+//! 高性能的松散四叉树
+//! 和八叉树相同的松散系数、节点收缩分化阈值及最大深度的语义，用于2D空间
+//! 代码是octree的2D分支，有意独立维护，修改octree的算法时需要同步修改这里
+//! 每个四叉节点有4个子节点，编号0-3见child_aabb，4表示在节点自身的nodes上
+extern crate core;
+
+extern crate cgmath;
+extern crate collision;
+extern crate map;
+extern crate slab;
+use std::mem;
+
+
+use collision::{Aabb, Aabb2, Contains};
+use cgmath::{BaseNum, Point2, Vector2};
+
+use map::{vecmap::VecMap};
+use slab::Slab;
+
+// aabb是否相交
+#[inline]
+pub fn intersects<S:BaseNum>(a: &Aabb2<S>, b: &Aabb2<S>) -> bool {
+	a.min.x < b.max.x
+	&& a.max.x > b.min.x
+	&& a.min.y < b.max.y
+	&& a.max.y > b.min.y
+}
+
+/// aabb的查询函数的参数
+pub struct AbQueryArgs<S:BaseNum, T> {
+	aabb: Aabb2<S>,
+	result: Vec<(usize, T)>,
+}
+impl<S: BaseNum, T:Clone> AbQueryArgs<S, T> {
+	pub fn new(aabb: Aabb2<S>) -> AbQueryArgs<S, T> {
+		AbQueryArgs{
+		aabb: aabb,
+		result: Vec::new(),
+		}
+	}
+	pub fn result(&mut self) -> Vec<(usize, T)> {
+		mem::replace(&mut self.result, Vec::new())
+	}
+}
+
+/// aabb的ab查询函数, aabb的quad查询函数应该使用intersects
+pub fn ab_query_func<S:BaseNum, T:Clone>(arg: &mut AbQueryArgs<S, T>, id: usize, aabb: &Aabb2<S>, bind: &T) {
+	if intersects(&arg.aabb, aabb) {
+		arg.result.push((id, bind.clone()));
+	}
+}
+
+/// QuadTree
+pub struct Tree<S: BaseNum, T> {
+	quad_slab: Slab<QuadNode<S>>,
+	ab_map: VecMap<AbNode<S, T>>,
+	loose_ratio: usize,     //松散系数，0-10000之间， 默认3000
+	adjust: (usize, usize), //小于min，节点收缩; 大于max，节点分化。默认(4, 5)
+	deep: usize,            // 最大深度
+	loose: Vector2<S>,      //第一层的松散大小
+	outer: NodeList, // 和根节点不相交的ab节点列表，及节点数量。 相交的放在root的nodes上了。 该AbNode的parent为0
+	dirty: (Vec<Vec<usize>>, usize, usize), // 脏的QuadNode节点, 及脏节点数量，及脏节点的起始层
+}
+
+impl<S: BaseNum, T> Tree<S, T> {
+	pub fn new(
+		root: Aabb2<S>,
+		loose_ratio: usize,
+		adjust_min: usize,
+		adjust_max: usize,
+		deep: usize,
+	) -> Tree<S, T> {
+		let d = root.dim();
+		let loose_ratio = if loose_ratio == 0 { LOOSE } else { loose_ratio };
+		let loose_ratio = if loose_ratio > LOOSE_MAX { LOOSE_MAX } else { loose_ratio };
+		let lr = S::from(loose_ratio).unwrap();
+		let w = S::from(10000).unwrap();
+		let loose = Vector2::new(d.x * lr / w, d.y * lr / w);
+		let mut s = Slab::new();
+		s.insert(QuadNode::new(root, loose.clone(), 0, 0, 0));
+		let deep = if deep == 0 || deep > DEEP_MAX {
+			DEEP_MAX
+		} else {
+			deep
+		};
+		let two = S::one() + S::one();
+		let deep = if S::from(1usize).unwrap() / two == S::zero() {
+			// 如果是整数空间，则必须计算最大深度，否则会出现物件不在子节点上
+			let mut i = 0;
+			let mut l = loose / two;
+			while i < deep && l.x > S::zero() && l.y > S::zero() {
+				l = l / two;
+				i += 1;
+			}
+			i
+		} else {
+			deep
+		};
+		let adjust_min = if adjust_min == 0 {
+			ADJUST_MIN
+		} else {
+			adjust_min
+		};
+		let adjust_max = if adjust_max == 0 {
+			ADJUST_MAX
+		} else {
+			adjust_max
+		};
+		let adjust_max = if adjust_min > adjust_max {
+			adjust_min
+		} else {
+			adjust_max
+		};
+		Tree {
+			quad_slab: s,
+			ab_map: VecMap::default(),
+			loose_ratio: loose_ratio,
+			adjust: (adjust_min, adjust_max),
+			deep: deep,
+			loose: loose,
+			outer: NodeList::new(),
+			dirty: (Vec::new(), 0, usize::max_value()),
+		}
+	}
+	// 获得松散系数
+	pub fn get_loose_ratio(&self) -> usize {
+		self.loose_ratio
+	}
+	// 获得节点收缩和分化的阈值
+	pub fn get_adjust(&self) -> (usize, usize) {
+		(self.adjust.0, self.adjust.1)
+	}
+	// 获得该aabb对应的层
+	pub fn get_layer(&self, aabb: &Aabb2<S>) -> usize {
+		calc_layer(&self.loose, &aabb.dim())
+	}
+	// 添加一个aabb及其绑定
+	pub fn add(&mut self, id: usize, aabb: Aabb2<S>, bind: T) {
+		let layer = calc_layer(&self.loose, &aabb.dim());
+		match self.ab_map.insert(id, AbNode::new(aabb, bind, layer)) {
+			Some(_) => panic!("duplicate id: {}", id),
+			_ => ()
+		}
+		let next = {
+			let node = unsafe { self.ab_map.get_unchecked_mut(id) };
+			let root = unsafe { self.quad_slab.get_unchecked_mut(1) };
+			if root.aabb.contains(&node.aabb) {
+				set_tree_dirty(
+					&mut self.dirty,
+					down(&mut self.quad_slab, self.adjust.1, self.deep, 1, node, id),
+				);
+			} else if intersects(&root.aabb, &node.aabb) {
+				// 相交的放在root的nodes上
+				node.parent = 1;
+				node.next = root.nodes.head;
+				root.nodes.push(id);
+			} else {
+				// 和根节点不相交的ab节点, 该AbNode的parent为0
+				node.next = self.outer.head;
+				self.outer.push(id);
+			}
+			node.next
+		};
+		if next > 0 {
+			let n = unsafe { self.ab_map.get_unchecked_mut(next) };
+			n.prev = id;
+		}
+	}
+	// 获取指定id的aabb及其绑定
+	pub fn get(&self, id: usize) -> Option<(&Aabb2<S>, &T)> {
+		match self.ab_map.get(id) {
+		Some(node) => Some((&node.aabb, &node.bind)),
+		_ => None,
+		}
+	}
+	// 获取指定id的aabb及其绑定
+	pub unsafe fn get_unchecked(&self, id: usize) -> (&Aabb2<S>, &T) {
+		let node = self.ab_map.get_unchecked(id);
+		(&node.aabb, &node.bind)
+	}
+	// 更新指定id的aabb
+	pub fn update(&mut self, id: usize, aabb: Aabb2<S>) -> bool {
+		let r = match self.ab_map.get_mut(id) {
+		Some(node) => {
+			node.layer = calc_layer(&self.loose, &aabb.dim());
+			node.aabb = aabb;
+			update(
+			&mut self.quad_slab,
+			&self.adjust,
+			self.deep,
+			&mut self.outer,
+			&mut self.dirty,
+			id,
+			node,
+			)
+		}
+		_ => return false,
+		};
+		remove_add(self, id, r);
+		true
+	}
+	// 移动指定id的aabb，性能比update要略好
+	pub fn shift(&mut self, id: usize, distance: Vector2<S>) -> bool {
+		let r = match self.ab_map.get_mut(id) {
+		Some(node) => {
+			node.aabb = Aabb2::new(node.aabb.min + distance, node.aabb.max + distance);
+			update(
+			&mut self.quad_slab,
+			&self.adjust,
+			self.deep,
+			&mut self.outer,
+			&mut self.dirty,
+			id,
+			node,
+			)
+		}
+		_ => return false,
+		};
+		remove_add(self, id, r);
+		true
+	}
+	// 获取指定id的可写绑定
+	pub unsafe fn get_mut(&mut self, id: usize) -> Option<&mut T> {
+		match self.ab_map.get_mut(id) {
+			Some(n) => Some(&mut n.bind),
+			_ => None
+		}
+	}
+	// 获取指定id的可写绑定
+	pub unsafe fn get_unchecked_mut(&mut self, id: usize) -> &mut T {
+		let node = self.ab_map.get_unchecked_mut(id);
+		&mut node.bind
+	}
+	// 更新指定id的绑定
+	pub fn update_bind(&mut self, id: usize, bind: T) -> bool {
+		match self.ab_map.get_mut(id) {
+		Some(node) => {
+			node.bind = bind;
+			true
+		}
+		_ => false,
+		}
+	}
+	// 移除指定id的aabb及其绑定
+	pub fn remove(&mut self, id: usize) -> Option<(Aabb2<S>, T)> {
+		let node = match self.ab_map.remove(id) {
+			Some(n) => n,
+			_ => return None
+		};
+		if node.parent > 0 {
+		let (p, c) = {
+			let parent = unsafe { self.quad_slab.get_unchecked_mut(node.parent) };
+			if node.parent_child < 4 {
+			// 在节点的childs上
+			match parent.childs[node.parent_child] {
+				ChildNode::Ab(ref mut ab) => ab.remove(&mut self.ab_map, node.prev, node.next),
+				_ => panic!("invalid state"),
+			}
+			} else {
+			// 在节点的nodes上
+			parent.nodes.remove(&mut self.ab_map, node.prev, node.next);
+			}
+			(parent.parent, parent.parent_child)
+		};
+		remove_up(&mut self.quad_slab, self.adjust.0, &mut self.dirty, p, c);
+		} else {
+		// 表示在outer上
+		self.outer.remove(&mut self.ab_map, node.prev, node.next);
+		}
+		Some((node.aabb, node.bind))
+	}
+	// 整理方法，只有整理方法才会创建或销毁QuadNode
+	pub fn collect(&mut self) {
+		let mut count = self.dirty.1;
+		if count == 0 {
+		return;
+		}
+		for i in self.dirty.2..self.dirty.0.len() {
+		let vec = unsafe { self.dirty.0.get_unchecked_mut(i) };
+		let c = vec.len();
+		if c == 0 {
+			continue;
+		}
+		for j in 0..c {
+			let quad_id = unsafe { vec.get_unchecked(j) };
+			collect(
+			&mut self.quad_slab,
+			&mut self.ab_map,
+			&self.adjust,
+			self.deep,
+			*quad_id,
+			);
+		}
+		vec.clear();
+		if count <= c {
+			break;
+		}
+		count -= c;
+		}
+		self.dirty.1 = 0;
+		self.dirty.2 = usize::max_value();
+	}
+
+	// 查询空间内及相交的ab节点
+	pub fn query<A, B>(
+		&self,
+		quad_arg: &A,
+		quad_func: fn(arg: &A, aabb: &Aabb2<S>) -> bool,
+		ab_arg: &mut B,
+		ab_func: fn(arg: &mut B, id: usize, aabb: &Aabb2<S>, bind: &T),
+	) {
+		query(
+		&self.quad_slab,
+		&self.ab_map,
+		1,
+		&mut |aabb| quad_func(quad_arg, aabb),
+		&mut |id, aabb, bind| ab_func(ab_arg, id, aabb, bind),
+		)
+	}
+	// 查询空间内及相交的ab节点，使用闭包
+	pub fn query_with<F, G>(&self, mut quad_func: F, mut ab_func: G)
+	where
+		F: FnMut(&Aabb2<S>) -> bool,
+		G: FnMut(usize, &Aabb2<S>, &T),
+	{
+		query(&self.quad_slab, &self.ab_map, 1, &mut quad_func, &mut ab_func)
+	}
+	// 查询空间外的ab节点
+	pub fn query_outer<B>(
+		&self,
+		arg: &mut B,
+		func: fn(arg: &mut B, id: usize, aabb: &Aabb2<S>, bind: &T),
+	) {
+		self.query_outer_with(|id, aabb, bind| func(arg, id, aabb, bind))
+	}
+	// 查询空间外的ab节点，使用闭包
+	pub fn query_outer_with<F: FnMut(usize, &Aabb2<S>, &T)>(&self, mut func: F) {
+		let mut id = self.outer.head;
+		while id > 0 {
+		let ab = unsafe { self.ab_map.get_unchecked(id) };
+		func(id, &ab.aabb, &ab.bind);
+		id = ab.next;
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+struct NodeList {
+	head: usize,
+	len: usize,
+}
+impl NodeList {
+	#[inline]
+	pub fn new() -> NodeList {
+		NodeList { head: 0, len: 0 }
+	}
+	#[inline]
+	pub fn push(&mut self, id: usize) {
+		self.head = id;
+		self.len += 1;
+	}
+	#[inline]
+	pub fn remove<S: BaseNum, T>(&mut self, map: &mut VecMap<AbNode<S, T>>, prev: usize, next: usize) {
+		if prev > 0 {
+			let node = unsafe { map.get_unchecked_mut(prev) };
+			node.next = next;
+		} else {
+			self.head = next;
+		}
+		if next > 0 {
+			let node = unsafe { map.get_unchecked_mut(next) };
+			node.prev = prev;
+		}
+		self.len -= 1;
+	}
+}
+
+const LOOSE: usize = 3000;
+const LOOSE_MAX: usize = 5000;
+const DEEP_MAX: usize = 24;
+const ADJUST_MIN: usize = 4;
+const ADJUST_MAX: usize = 5;
+
+#[derive(Debug, Clone)]
+struct QuadNode<S: BaseNum> {
+	aabb: Aabb2<S>,         // 包围盒
+	loose: Vector2<S>,      // 本层的松散值
+	parent: usize,          // 父四叉节点
+	parent_child: usize,    // 对应父四叉节点childs的位置
+	childs: [ChildNode; 4], // 子四叉节点
+	layer: usize,           // 表示第几层， 根据aabb大小，决定最低为第几层
+	nodes: NodeList,        // 匹配本层大小的ab节点列表，及节点数量
+	dirty: usize, // 脏标记, 低4位(1、2、4、8)对应0-3号子节点被修改。添加了节点，并且某个子四叉节点(AbNode)的数量超过阈值，可能分化。删除了节点，并且自己及其下ab节点的数量超过阈值，可能收缩
+}
+impl<S: BaseNum> QuadNode<S> {
+	#[inline]
+	pub fn new(
+		aabb: Aabb2<S>,
+		loose: Vector2<S>,
+		parent: usize,
+		child: usize,
+		layer: usize,
+	) -> QuadNode<S> {
+		QuadNode {
+		aabb: aabb,
+		loose: loose,
+		parent: parent,
+		parent_child: child,
+		childs: [
+			ChildNode::Ab(NodeList::new()),
+			ChildNode::Ab(NodeList::new()),
+			ChildNode::Ab(NodeList::new()),
+			ChildNode::Ab(NodeList::new()),
+		],
+		layer: layer,
+		nodes: NodeList::new(),
+		dirty: 0,
+		}
+	}
+}
+#[derive(Debug, Clone)]
+enum ChildNode {
+	Quad(usize, usize), // 对应的QuadNode, 及其下ab节点的数量
+	Ab(NodeList),       // ab节点列表，及节点数量
+}
+
+#[derive(Debug, Clone)]
+struct AbNode<S: BaseNum, T> {
+	aabb: Aabb2<S>,      // 包围盒
+	bind: T,             // 绑定
+	layer: usize,        // 表示第几层， 根据aabb大小，决定最低为第几层
+	parent: usize,       // 父四叉节点
+	parent_child: usize, // 父四叉节点所在的子四叉节点， 4表示不在子四叉节点上
+	prev: usize,         // 前ab节点
+	next: usize,         // 后ab节点
+}
+impl<S: BaseNum, T> AbNode<S, T> {
+	pub fn new(aabb: Aabb2<S>, bind: T, layer: usize) -> AbNode<S, T> {
+		AbNode {
+		aabb: aabb,
+		bind: bind,
+		layer: layer,
+		parent: 0,
+		parent_child: 4,
+		prev: 0,
+		next: 0,
+		}
+	}
+}
+
+// 计算该aabb对应的层
+#[inline]
+fn calc_layer<S: BaseNum>(loose: &Vector2<S>, el: &Vector2<S>) -> usize {
+	let x = if el.x == S::zero() {
+		usize::max_value()
+	} else {
+		(loose.x / el.x).to_usize().unwrap()
+	};
+	let y = if el.y == S::zero() {
+		usize::max_value()
+	} else {
+		(loose.y / el.y).to_usize().unwrap()
+	};
+	let min = x.min(y);
+	(mem::size_of::<usize>() << 3) - (min.leading_zeros() as usize)
+}
+
+// 计算指定子节点的松散包围盒，0为x、y都靠max的一侧，1为x靠min，2为y靠min，3为x、y都靠min的一侧
+#[inline]
+fn child_aabb<S: BaseNum>(aabb: &Aabb2<S>, loose: &Vector2<S>, child: usize) -> Aabb2<S> {
+	let two = S::one() + S::one();
+	let x1 = (aabb.min.x + aabb.max.x - loose.x) / two;
+	let y1 = (aabb.min.y + aabb.max.y - loose.y) / two;
+	let x2 = (aabb.min.x + aabb.max.x + loose.x) / two;
+	let y2 = (aabb.min.y + aabb.max.y + loose.y) / two;
+	match child {
+		0 => Aabb2::new(Point2::new(x1, y1), aabb.max()),
+		1 => Aabb2::new(Point2::new(aabb.min.x, y1), Point2::new(x2, aabb.max.y)),
+		2 => Aabb2::new(Point2::new(x1, aabb.min.y), Point2::new(aabb.max.x, y2)),
+		_ => Aabb2::new(aabb.min(), Point2::new(x2, y2)),
+	}
+}
+
+// 检查自己、父亲及兄弟节点，返回包含node的子节点0-3，不被父节点包含返回5
+fn check_contain<S: BaseNum>(
+	parent: &Aabb2<S>,
+	loose: &Vector2<S>,
+	node: &Aabb2<S>,
+	child: usize,
+) -> usize {
+	if child_aabb(parent, loose, child).contains(node) {
+		return child;
+	}
+	if !parent.contains(node) {
+		return 5;
+	}
+	for i in 0..3 {
+		if child_aabb(parent, loose, i).contains(node) {
+			return i;
+		}
+	}
+	return 3;
+}
+// ab节点下降
+fn down<S: BaseNum, T>(
+	slab: &mut Slab<QuadNode<S>>,
+	adjust: usize,
+	deep: usize,
+	quad_id: usize,
+	node: &mut AbNode<S, T>,
+	id: usize,
+) -> (usize, usize) {
+	let parent = unsafe { slab.get_unchecked_mut(quad_id) };
+	if parent.layer >= node.layer {
+		node.parent = quad_id;
+		node.next = parent.nodes.head;
+		parent.nodes.push(id);
+		return (0, 0);
+	}
+	for i in 0..4 {
+		if child_aabb(&parent.aabb, &parent.loose, i).contains(&node.aabb) {
+			match parent.childs[i] {
+			ChildNode::Quad(quad, ref mut num) => {
+				*num += 1;
+				return down(slab, adjust, deep, quad, node, id);
+			}
+			ChildNode::Ab(ref mut list) => {
+				node.parent = quad_id;
+				node.parent_child = i;
+				node.next = list.head;
+				list.push(id);
+				if list.len > adjust && parent.layer < deep {
+				return set_dirty(&mut parent.dirty, i, parent.layer, quad_id);
+				}
+				return (0, 0)
+			}
+			}
+		}
+	}
+	(0, 0)
+}
+// 更新aabb
+fn update<S: BaseNum, T>(
+	slab: &mut Slab<QuadNode<S>>,
+	adjust: &(usize, usize),
+	deep: usize,
+	outer: &mut NodeList,
+	dirty: &mut (Vec<Vec<usize>>, usize, usize),
+	id: usize,
+	node: &mut AbNode<S, T>,
+) -> Option<(usize, usize, usize, usize, usize)> {
+	let old_p = node.parent;
+	if old_p > 0 {
+		let old_c = node.parent_child;
+		let mut parent = unsafe { slab.get_unchecked_mut(old_p) };
+		if node.layer > parent.layer {
+			// ab节点能在当前Quad节点的容纳范围
+			// 获得新位置
+			let child = check_contain(&parent.aabb, &parent.loose, &node.aabb, old_c);
+			if old_c == child {
+				return None;
+			}
+			if child < 4 {
+				let prev = node.prev;
+				let next = node.next;
+				node.prev = 0;
+				// 移动到兄弟节点
+				match parent.childs[child] {
+				ChildNode::Quad(quad, ref mut num) => {
+					*num += 1;
+					node.parent_child = 4;
+					set_tree_dirty(dirty, down(slab, adjust.1, deep, quad, node, id));
+					return Some((old_p, old_c, prev, next, node.next));
+				}
+				ChildNode::Ab(ref mut list) => {
+					node.parent_child = child;
+					node.next = list.head;
+					list.push(id);
+					if list.len > adjust.1 && parent.layer < deep {
+					set_tree_dirty(dirty, set_dirty(&mut parent.dirty, child, parent.layer, old_p));
+					}
+					return Some((old_p, old_c, prev, next, node.next));
+				}
+				}
+			}
+		// 需要向上
+		} else if node.layer == parent.layer {
+			if parent.aabb.contains(&node.aabb) {
+				if old_c == 4 {
+					return None;
+				}
+				let prev = node.prev;
+				let next = node.next;
+				node.prev = 0;
+				// 从child 移到 nodes
+				node.parent_child = 4;
+				node.next = parent.nodes.head;
+				parent.nodes.push(id);
+				return Some((old_p, old_c, prev, next, node.next));
+			}
+		// 在当前节点外
+		} else {
+		// 比当前节点大
+		};
+		let prev = node.prev;
+		let next = node.next;
+		if old_p > 1 {
+			// 向上移动
+			let mut p = parent.parent;
+			let mut c = parent.parent_child;
+			loop {
+				parent = unsafe { slab.get_unchecked_mut(p) };
+				match parent.childs[c] {
+					ChildNode::Quad(_, ref mut num) => {
+						*num -= 1;
+						if *num < adjust.0 {
+							let d = set_dirty(&mut parent.dirty, c, parent.layer, p);
+							if d.1 > 0 {
+								set_tree_dirty(dirty, d);
+							}
+						}
+					}
+					_ => panic!("invalid state"),
+				}
+				if parent.layer <= node.layer && parent.aabb.contains(&node.aabb) {
+					node.prev = 0;
+					node.parent_child = 4;
+					set_tree_dirty(dirty, down(slab, adjust.1, deep, p, node, id));
+					return Some((old_p, old_c, prev, next, node.next));
+				}
+				p = parent.parent;
+				c = parent.parent_child;
+				if p == 0 {
+					break;
+				}
+			}
+		}
+		// 判断根节点是否相交
+		if intersects(&parent.aabb, &node.aabb) {
+			if old_p == 1 && old_c == 4 {
+				return None
+			}
+			// 相交的放在root的nodes上
+			node.parent = 1;
+			node.next = parent.nodes.head;
+			parent.nodes.push(id);
+		} else {
+			node.parent = 0;
+			node.next = outer.head;
+			outer.push(id);
+		}
+		node.prev = 0;
+		node.parent_child = 4;
+		return Some((old_p, old_c, prev, next, node.next));
+	} else {
+		// 边界外物体更新
+		let root = unsafe { slab.get_unchecked_mut(1) };
+		if intersects(&root.aabb, &node.aabb) {
+			// 判断是否相交或包含
+			let prev = node.prev;
+			let next = node.next;
+			node.prev = 0;
+			node.parent_child = 4;
+			if root.aabb.contains(&node.aabb) {
+				set_tree_dirty(dirty, down(slab, adjust.1, deep, 1, node, id));
+			} else {
+				// 相交的放在root的nodes上
+				node.parent = 1;
+				node.next = root.nodes.head;
+				root.nodes.push(id);
+			}
+			Some((0, 0, prev, next, node.next))
+		} else {
+			// 表示还在outer上
+			None
+		}
+	}
+}
+// 从NodeList中移除，并可能添加
+fn remove_add<S: BaseNum, T>(
+	tree: &mut Tree<S, T>,
+	id: usize,
+	r: Option<(usize, usize, usize, usize, usize)>,
+) {
+	// 从NodeList中移除
+	if let Some((rid, child, prev, next, cur_next)) = r {
+		if rid > 0 {
+			let quad = unsafe { tree.quad_slab.get_unchecked_mut(rid) };
+			if child < 4 {
+				match quad.childs[child] {
+					ChildNode::Ab(ref mut ab) => ab.remove(&mut tree.ab_map, prev, next),
+					_ => panic!("invalid state"),
+				}
+			} else {
+				quad.nodes.remove(&mut tree.ab_map, prev, next);
+			}
+		} else {
+			tree.outer.remove(&mut tree.ab_map, prev, next);
+		}
+		if cur_next > 0 {
+			let n = unsafe { tree.ab_map.get_unchecked_mut(cur_next) };
+			n.prev = id;
+		}
+	}
+}
+
+// 移除时，向上修改数量，并可能设脏
+#[inline]
+fn remove_up<S: BaseNum>(
+	slab: &mut Slab<QuadNode<S>>,
+	adjust: usize,
+	dirty: &mut (Vec<Vec<usize>>, usize, usize),
+	parent: usize,
+	child: usize,
+) {
+	if parent == 0 {
+		return;
+	}
+	let (p, c) = {
+		let node = unsafe { slab.get_unchecked_mut(parent) };
+		match node.childs[child] {
+		ChildNode::Quad(_, ref mut num) => {
+			*num -= 1;
+			if *num < adjust {
+			let d = set_dirty(&mut node.dirty, child, node.layer, parent);
+			if d.1 > 0 {
+				set_tree_dirty(dirty, d);
+			}
+			}
+		}
+		_ => panic!("invalid state"),
+		}
+		(node.parent, node.parent_child)
+	};
+	remove_up(slab, adjust, dirty, p, c);
+}
+
+#[inline]
+fn set_dirty(dirty: &mut usize, index: usize, layer: usize, rid: usize) -> (usize, usize) {
+	if *dirty == 0 {
+		*dirty |= 1 << index;
+		return (layer, rid);
+	}
+	*dirty |= 1 << index;
+	return (0, 0);
+}
+// 设置脏标记
+#[inline]
+fn set_tree_dirty(dirty: &mut (Vec<Vec<usize>>, usize, usize), (layer, rid): (usize, usize)) {
+	if rid == 0 {
+		return;
+	}
+	dirty.1 += 1;
+	if dirty.2 > layer {
+		dirty.2 = layer;
+	}
+	if dirty.0.len() <= layer {
+		for _ in dirty.0.len()..layer + 1 {
+		dirty.0.push(Vec::new())
+		}
+	}
+	let vec = unsafe { dirty.0.get_unchecked_mut(layer) };
+	vec.push(rid);
+}
+
+// 创建指定的子节点
+fn create_child<S: BaseNum>(
+	aabb: &Aabb2<S>,
+	loose: &Vector2<S>,
+	layer: usize,
+	parent_id: usize,
+	child: usize,
+) -> QuadNode<S> {
+	let two = S::one() + S::one();
+	return QuadNode::new(child_aabb(aabb, loose, child), loose / two, parent_id, child, layer + 1);
+}
+
+// 整理方法，只有整理方法才会创建或销毁QuadNode
+fn collect<S: BaseNum, T>(
+	quad_slab: &mut Slab<QuadNode<S>>,
+	ab_map: &mut VecMap<AbNode<S, T>>,
+	adjust: &(usize, usize),
+	deep: usize,
+	parent_id: usize,
+) {
+	let (dirty, childs, ab, loose, layer) = {
+		let parent = match quad_slab.get_mut(parent_id) {
+			Some(quad) => {
+				quad
+			},
+			_ => return
+		};
+		let dirty = parent.dirty;
+		if parent.dirty == 0 {
+			return;
+		}
+		parent.dirty = 0;
+		(dirty, parent.childs.clone(), parent.aabb.clone(), parent.loose.clone(), parent.layer)
+	};
+	for i in 0..4 {
+		if dirty & (1 << i) != 0 {
+			match childs[i] {
+				ChildNode::Quad(quad, num) if num < adjust.0 => {
+				let mut list = NodeList::new();
+				shrink(quad_slab, ab_map, parent_id, i, quad, &mut list);
+				let parent = unsafe { quad_slab.get_unchecked_mut(parent_id) };
+				parent.childs[i] = ChildNode::Ab(list);
+				}
+				ChildNode::Ab(ref list) if list.len > adjust.1 => {
+				let child_id = split(quad_slab, ab_map, adjust, deep, list, &ab, &loose, layer, parent_id, i);
+				let parent = unsafe { quad_slab.get_unchecked_mut(parent_id) };
+				parent.childs[i] = ChildNode::Quad(child_id, list.len);
+				}
+				_ => (),
+			}
+		}
+	}
+}
+// 收缩QuadNode
+fn shrink<S: BaseNum, T>(
+	quad_slab: &mut Slab<QuadNode<S>>,
+	ab_map: &mut VecMap<AbNode<S, T>>,
+	parent: usize,
+	parent_child: usize,
+	quad_id: usize,
+	result: &mut NodeList,
+) {
+	let node = quad_slab.remove(quad_id);
+	if node.nodes.len > 0 {
+		shrink_merge(ab_map, parent, parent_child, &node.nodes, result);
+	}
+	for i in 0..4 {
+		match node.childs[i] {
+			ChildNode::Ab(ref list) if list.len > 0 => {
+			shrink_merge(ab_map, parent, parent_child, &list, result);
+			}
+			ChildNode::Quad(quad, _) => {
+			shrink(quad_slab, ab_map, parent, parent_child, quad, result);
+			}
+			_ => (),
+		}
+	}
+}
+// 合并ab列表到结果列表中
+#[inline]
+fn shrink_merge<S: BaseNum, T>(
+	ab_map: &mut VecMap<AbNode<S, T>>,
+	parent: usize,
+	parent_child: usize,
+	list: &NodeList,
+	result: &mut NodeList,
+) {
+	let old = result.head;
+	result.head = list.head;
+	result.len += list.len;
+	let mut id = list.head;
+	loop {
+		let ab = unsafe { ab_map.get_unchecked_mut(id) };
+		ab.parent = parent;
+		ab.parent_child = parent_child;
+		if ab.next == 0 {
+		ab.next = old;
+		break;
+		}
+		id = ab.next;
+	}
+	if old > 0 {
+		let ab = unsafe { ab_map.get_unchecked_mut(old) };
+		ab.prev = id;
+	}
+}
+
+// 分裂出QuadNode
+#[inline]
+fn split<S: BaseNum, T>(
+	quad_slab: &mut Slab<QuadNode<S>>,
+	ab_map: &mut VecMap<AbNode<S, T>>,
+	adjust: &(usize, usize),
+	deep: usize,
+	list: &NodeList,
+	parent_ab: &Aabb2<S>,
+	parent_loose: &Vector2<S>,
+	parent_layer: usize,
+	parent_id: usize,
+	child: usize,
+) -> usize {
+	let quad = create_child(parent_ab, parent_loose, parent_layer, parent_id, child);
+	let quad_id = quad_slab.insert(quad);
+	let quad = unsafe { quad_slab.get_unchecked_mut(quad_id) };
+	if split_down(ab_map, adjust.1, deep, quad, quad_id, list) > 0 {
+		collect(quad_slab, ab_map, adjust, deep, quad_id);
+	}
+	quad_id
+}
+// 将ab节点列表放到分裂出来的四叉节点上
+fn split_down<S: BaseNum, T>(
+	map: &mut VecMap<AbNode<S, T>>,
+	adjust: usize,
+	deep: usize,
+	parent: &mut QuadNode<S>,
+	parent_id: usize,
+	list: &NodeList,
+) -> usize {
+	let mut id = list.head;
+	while id > 0 {
+		let node = unsafe { map.get_unchecked_mut(id) };
+		let nid = id;
+		id = node.next;
+		node.prev = 0;
+		if parent.layer >= node.layer {
+			node.parent = parent_id;
+			node.parent_child = 4;
+			node.next = parent.nodes.head;
+			parent.nodes.push(nid);
+			continue;
+		}
+		for i in 0..4 {
+			if child_aabb(&parent.aabb, &parent.loose, i).contains(&node.aabb) {
+				match parent.childs[i] {
+				ChildNode::Ab(ref mut list) => {
+					node.parent = parent_id;
+					node.parent_child = i;
+					node.next = list.head;
+					list.push(nid);
+					if list.len > adjust && parent.layer < deep {
+					set_dirty(&mut parent.dirty, i, parent.layer, parent_id);
+					}
+					break;
+				}
+				_ => panic!("invalid state"),
+				}
+			}
+		}
+	}
+	fix_prev(map, parent.nodes.head);
+	for i in 0..4 {
+		match parent.childs[i] {
+		ChildNode::Ab(ref list) => fix_prev(map, list.head),
+		_ => (), // panic
+		}
+	}
+	parent.dirty
+}
+// 修复prev
+#[inline]
+fn fix_prev<S: BaseNum, T>(
+	map: &mut VecMap<AbNode<S, T>>,
+	mut head: usize,
+) {
+	if head == 0 {
+		return;
+	}
+	let node = unsafe { map.get_unchecked(head) };
+	let mut next = node.next;
+	while next > 0 {
+		let node = unsafe { map.get_unchecked_mut(next) };
+		node.prev = head;
+		head = next;
+		next = node.next;
+	}
+}
+
+// 查询空间内及相交的ab节点
+fn query<S: BaseNum, T, F, G>(
+	quad_slab: &Slab<QuadNode<S>>,
+	ab_map: &VecMap<AbNode<S, T>>,
+	quad_id: usize,
+	quad_func: &mut F,
+	ab_func: &mut G,
+) where
+	F: FnMut(&Aabb2<S>) -> bool,
+	G: FnMut(usize, &Aabb2<S>, &T),
+{
+	let node = unsafe { quad_slab.get_unchecked(quad_id) };
+	let mut id = node.nodes.head;
+	while id > 0 {
+		let ab = unsafe { ab_map.get_unchecked(id) };
+		ab_func(id, &ab.aabb, &ab.bind);
+		id = ab.next;
+	}
+	for i in 0..4 {
+		match node.childs[i] {
+			ChildNode::Quad(quad, ref num) if *num > 0 => {
+			if quad_func(&child_aabb(&node.aabb, &node.loose, i)) {
+				query(quad_slab, ab_map, quad, quad_func, ab_func);
+			}
+			}
+			ChildNode::Ab(ref list) if list.head > 0 => {
+			if quad_func(&child_aabb(&node.aabb, &node.loose, i)) {
+				let mut id = list.head;
+				while id > 0 {
+				let ab = unsafe { ab_map.get_unchecked(id) };
+				ab_func(id, &ab.aabb, &ab.bind);
+				id = ab.next;
+				}
+			}
+			}
+			_ => (),
+		}
+	}
+}
+
+#[cfg(test)]
+fn check_list<S: BaseNum, T>(
+	ab_map: &VecMap<AbNode<S, T>>,
+	parent: usize,
+	parent_child: usize,
+	list: &NodeList,
+) -> usize {
+	let mut id = list.head;
+	let mut prev = 0;
+	let mut i = 0;
+	while id > 0 {
+		let ab = unsafe { ab_map.get_unchecked(id) };
+		assert_eq!(ab.prev, prev);
+		assert_eq!(ab.parent, parent);
+		assert_eq!(ab.parent_child, parent_child);
+		prev = id;
+		id = ab.next;
+		i += 1;
+	}
+	assert_eq!(i, list.len);
+	i
+}
+#[cfg(test)]
+fn check_tree<S: BaseNum, T>(tree: &Tree<S, T>) {
+	let mut count = check_list(&tree.ab_map, 0, 4, &tree.outer);
+	for (id, node) in tree.quad_slab.iter() {
+		count += check_list(&tree.ab_map, id, 4, &node.nodes);
+		for i in 0..4 {
+			match node.childs[i] {
+				ChildNode::Ab(ref list) => count += check_list(&tree.ab_map, id, i, list),
+				ChildNode::Quad(quad, _) => assert_eq!(tree.quad_slab.get(quad).unwrap().parent, id),
+			}
+		}
+	}
+	assert_eq!(count, tree.ab_map.len());
+}
+
+#[test]
+fn test1(){
+	let mut tree = Tree::new(Aabb2::new(Point2::new(0f32,0f32), Point2::new(1000f32,1000f32)), 0, 0, 0, 0);
+	let mut list = Vec::new();
+	for i in 0..300 {
+		let x = ((i * 37) % 100) as f32 * 10.0;
+		let y = ((i * 53) % 100) as f32 * 10.0;
+		let s = (i % 7) as f32 * 3.0 + 1.0;
+		let aabb = Aabb2::new(Point2::new(x, y), Point2::new(x + s, y + s));
+		tree.add(i + 1, aabb.clone(), i + 1);
+		list.push(aabb);
+	}
+	tree.add(301, Aabb2::new(Point2::new(-10.0, 0.0), Point2::new(10.0, 10.0)), 301);
+	list.push(Aabb2::new(Point2::new(-10.0, 0.0), Point2::new(10.0, 10.0)));
+	tree.add(302, Aabb2::new(Point2::new(-30.0, 0.0), Point2::new(-20.0, 10.0)), 302);
+	list.push(Aabb2::new(Point2::new(-30.0, 0.0), Point2::new(-20.0, 10.0)));
+	check_tree(&tree);
+	tree.collect();
+	check_tree(&tree);
+	for i in 0..300 {
+		let d = Vector2::new(((i * 13) % 21) as f32 - 10.0, ((i * 7) % 21) as f32 - 10.0);
+		tree.shift(i + 1, d);
+		list[i] = Aabb2::new(list[i].min + d, list[i].max + d);
+	}
+	for i in 0..50 {
+		let aabb = Aabb2::new(Point2::new(i as f32 * 20.0, 0.0), Point2::new(i as f32 * 20.0 + 200.0, 100.0));
+		tree.update(i * 3 + 1, aabb.clone());
+		list[i * 3] = aabb;
+	}
+	for i in 0..40 {
+		tree.remove(i * 7 + 2);
+	}
+	check_tree(&tree);
+	tree.collect();
+	check_tree(&tree);
+	let aabb = Aabb2::new(Point2::new(-15.0f32, 100.0), Point2::new(400.0, 600.0));
+	let mut args: AbQueryArgs<f32, usize> = AbQueryArgs::new(aabb.clone());
+	tree.query(&aabb, intersects, &mut args, ab_query_func);
+	tree.query_outer(&mut args, ab_query_func);
+	let mut r: Vec<usize> = args.result().iter().map(|&(id, _)| id).collect();
+	r.sort();
+	let all: Vec<usize> = (0..list.len()).filter(|i| i % 7 != 1 || *i >= 280 || *i / 7 >= 40).filter(|i| intersects(&aabb, &list[*i])).map(|i| i + 1).collect();
+	assert_eq!(r, all);
+	for i in 0..300 {
+		tree.remove(i + 1);
+	}
+	tree.collect();
+	check_tree(&tree);
+	assert_eq!(tree.quad_slab.len(), 1);
+}
@@ -0,0 +1,148 @@
+//! Guillotine装箱算法
+//! 空闲区域用一组互不重叠的矩形表示，放入矩形后将所在的空闲矩形沿一条直线切成两个。
+//! 移除的矩形放回空闲列表，并和相邻的空闲矩形合并。实现简单，添加和移除都比较快。
+
+use cgmath::{Point2, Vector2};
+
+use slab::Slab;
+
+use {align_size, merge_rects, Atlas, Rect};
+
+/// 选择空闲矩形的策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FitHeuristic {
+  BestAreaFit,      // 面积剩余最少
+  BestShortSideFit, // 短边剩余最少
+  BestLongSideFit,  // 长边剩余最少
+  WorstAreaFit,     // 面积剩余最多
+}
+
+/// 切分空闲矩形的策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitHeuristic {
+  ShorterLeftoverAxis, // 沿剩余较短的边切分
+  LongerLeftoverAxis,  // 沿剩余较长的边切分
+  ShorterAxis,         // 沿空闲矩形较短的边切分
+  LongerAxis,          // 沿空闲矩形较长的边切分
+}
+
+/// Guillotine矩形表
+pub struct Guillotine {
+  size: Vector2<usize>,
+  align: usize,          // 对齐大小
+  fit: FitHeuristic,     // 选择空闲矩形的策略
+  split: SplitHeuristic, // 切分空闲矩形的策略
+  free: Vec<Rect>,       // 空闲矩形
+  rects: Slab<(Vector2<usize>, Point2<usize>)>, // 已分配的矩形大小及位置
+}
+
+impl Guillotine {
+  pub fn new(mut size: Vector2<usize>, align: usize, fit: FitHeuristic, split: SplitHeuristic) -> Self {
+    align_size(align, &mut size);
+    Guillotine {
+      size: size,
+      align: align,
+      fit: fit,
+      split: split,
+      free: vec![Rect::new(0, 0, size.x, size.y)],
+      rects: Slab::new(),
+    }
+  }
+  // 获得选择空闲矩形的策略
+  pub fn get_fit_heuristic(&self) -> FitHeuristic {
+    self.fit
+  }
+  // 获得切分空闲矩形的策略
+  pub fn get_split_heuristic(&self) -> SplitHeuristic {
+    self.split
+  }
+  // 计算放入空闲矩形的分数，越小越好
+  fn score(&self, free: &Rect, w: usize, h: usize) -> (usize, usize) {
+    let (lw, lh) = (free.w - w, free.h - h);
+    let (short, long) = if lw < lh { (lw, lh) } else { (lh, lw) };
+    match self.fit {
+      FitHeuristic::BestAreaFit => (free.w * free.h - w * h, short),
+      FitHeuristic::BestShortSideFit => (short, long),
+      FitHeuristic::BestLongSideFit => (long, short),
+      FitHeuristic::WorstAreaFit => (usize::max_value() - (free.w * free.h - w * h), 0),
+    }
+  }
+}
+
+impl Atlas for Guillotine {
+  fn get_size(&self) -> &Vector2<usize> {
+    &self.size
+  }
+  fn get_align(&self) -> usize {
+    self.align
+  }
+  fn count(&self) -> usize {
+    self.rects.len()
+  }
+  fn add(&mut self, size: Vector2<usize>) -> usize {
+    let mut asize = size.clone();
+    align_size(self.align, &mut asize);
+    let (w, h) = (asize.x, asize.y);
+    let mut best = None;
+    for (i, f) in self.free.iter().enumerate() {
+      if f.w < w || f.h < h {
+        continue;
+      }
+      let score = self.score(f, w, h);
+      match best {
+        Some((s, _)) if s <= score => (),
+        _ => best = Some((score, i)),
+      }
+    }
+    let f = match best {
+      Some((_, i)) => self.free.swap_remove(i),
+      _ => return 0,
+    };
+    // 剩余部分切成右边和下边两个空闲矩形，horizontal表示下边的矩形占满宽度
+    let horizontal = match self.split {
+      SplitHeuristic::ShorterLeftoverAxis => f.w - w <= f.h - h,
+      SplitHeuristic::LongerLeftoverAxis => f.w - w > f.h - h,
+      SplitHeuristic::ShorterAxis => f.w <= f.h,
+      SplitHeuristic::LongerAxis => f.w > f.h,
+    };
+    if f.h > h {
+      self.free.push(Rect::new(f.x, f.y + h, if horizontal { f.w } else { w }, f.h - h));
+    }
+    if f.w > w {
+      self.free.push(Rect::new(f.x + w, f.y, f.w - w, if horizontal { h } else { f.h }));
+    }
+    self.rects.insert((size, Point2 { x: f.x, y: f.y }))
+  }
+  fn get(&self, id: usize) -> Option<(&Vector2<usize>, &Point2<usize>)> {
+    self.rects.get(id).map(|&(ref size, ref pos)| (size, pos))
+  }
+  fn remove(&mut self, id: usize) -> (Vector2<usize>, Point2<usize>) {
+    let (size, pos) = self.rects.remove(id);
+    if self.rects.len() == 0 {
+      self.free = vec![Rect::new(0, 0, self.size.x, self.size.y)];
+    } else {
+      let mut asize = size.clone();
+      align_size(self.align, &mut asize);
+      self.free.push(Rect::new(pos.x, pos.y, asize.x, asize.y));
+      merge_rects(&mut self.free);
+    }
+    (size, pos)
+  }
+  fn extends(&mut self, mut size: Vector2<usize>) -> bool {
+    align_size(self.align, &mut size);
+    let w = if self.size.x > size.x { self.size.x } else { size.x };
+    let h = if self.size.y > size.y { self.size.y } else { size.y };
+    if w == self.size.x && h == self.size.y {
+      return false;
+    }
+    if w > self.size.x {
+      self.free.push(Rect::new(self.size.x, 0, w - self.size.x, self.size.y));
+    }
+    if h > self.size.y {
+      self.free.push(Rect::new(0, self.size.y, w, h - self.size.y));
+    }
+    self.size = Vector2 { x: w, y: h };
+    merge_rects(&mut self.free);
+    true
+  }
+}
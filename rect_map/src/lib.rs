@@ -3,20 +3,58 @@
 // 此类通常用于构建光照贴图、sprite贴图或将几个小纹理打包成大纹理。
 // 请注意，此类允许释放分配的矩形：即动态维护映射，因此可以根据矩形的生命周期添加/删除矩形。
 // 为了更好的利用空间，需要指定对齐大小，推荐按16来对齐。
-// 如果需要在分配的矩形周围留有一个空白，请自行维护。
+// 可以设置自动扩大的策略，try_add放不下时按策略扩大总大小，并返回新的大小以便重新分配纹理。
+// 如果需要在分配的矩形周围留有一个空白，可以设置边距，分配的区域包含边距，获取的是内部的矩形。
+// 边距区域需要用矩形的边缘像素填充，以免采样时出现渗色，可用extrusion计算要填充的区域。
+// 长时间动态添加/删除后空闲区域会变得零碎，可用repack重新紧凑地放置所有矩形，id保持不变。
+// 移除大部分矩形后，可用shrink_to_fit或try_resize缩小总大小，同样返回需要移动的矩形。
+// 离线打包时如果预先知道所有矩形的大小，可用pack批量装箱，结果和矩形的顺序无关。
 
 extern crate cgmath;
 
 extern crate slab;
 
+use std::error::Error;
+use std::fmt;
+
 use cgmath::{Point2, Vector2};
 
 use slab::Slab;
 
+pub mod max_rects;
+pub mod skyline;
+pub mod guillotine;
+
+pub use max_rects::MaxRects;
+pub use skyline::Skyline;
+pub use guillotine::Guillotine;
+
+/// 矩形分配器的通用接口，RectMap、MaxRects、Skyline、Guillotine都实现了该接口
+pub trait Atlas {
+  // 获取总大小
+  fn get_size(&self) -> &Vector2<usize>;
+  // 获得对齐大小
+  fn get_align(&self) -> usize;
+  // 获取总的矩形数量
+  fn count(&self) -> usize;
+  // 添加一个矩形，返回矩形的id，放不下返回0
+  fn add(&mut self, size: Vector2<usize>) -> usize;
+  // 获取指定id矩形的大小和位置
+  fn get(&self, id: usize) -> Option<(&Vector2<usize>, &Point2<usize>)>;
+  // 移除指定id的矩形，返回矩形的大小和位置
+  fn remove(&mut self, id: usize) -> (Vector2<usize>, Point2<usize>);
+  // 扩大总大小
+  fn extends(&mut self, size: Vector2<usize>) -> bool;
+}
+
 /// 矩形表
 pub struct RectMap {
   slab: Slab<Node>,
   align: usize, // 对齐大小
+  padding: usize, // 默认的边距
+  rotate: bool, // 是否允许旋转90度放入
+  grow: Grow, // 自动扩大的策略
+  max_size: Vector2<usize>, // 自动扩大的最大大小
 }
 
 impl RectMap {
@@ -32,6 +70,10 @@ impl RectMap {
     RectMap {
       slab: s,
       align: align,
+      padding: 0,
+      rotate: false,
+      grow: Grow::None,
+      max_size: size,
     }
   }
   // 获取总大小
@@ -43,6 +85,33 @@ impl RectMap {
   pub fn get_align(&self) -> usize {
     self.align
   }
+  // 获得默认的边距
+  pub fn get_padding(&self) -> usize {
+    self.padding
+  }
+  // 设置默认的边距，只影响之后添加的矩形
+  pub fn set_padding(&mut self, padding: usize) {
+    self.padding = padding;
+  }
+  // 是否允许旋转放入
+  pub fn get_rotate(&self) -> bool {
+    self.rotate
+  }
+  // 设置是否允许旋转放入，允许时如果旋转90度放入更合适，则旋转放入
+  pub fn set_rotate(&mut self, rotate: bool) {
+    self.rotate = rotate;
+  }
+  // 获得自动扩大的策略及最大大小
+  pub fn get_grow(&self) -> (Grow, &Vector2<usize>) {
+    (self.grow, &self.max_size)
+  }
+  // 设置自动扩大的策略及最大大小，最大大小按对齐大小向下取整
+  pub fn set_grow(&mut self, grow: Grow, mut max_size: Vector2<usize>) {
+    max_size.x -= max_size.x % self.align;
+    max_size.y -= max_size.y % self.align;
+    self.grow = grow;
+    self.max_size = max_size;
+  }
   // 获取总的矩形数量
   pub fn count(&self) -> usize {
     let node = unsafe { self.slab.get_unchecked(1) };
@@ -51,85 +120,220 @@ impl RectMap {
       _ => 0,
     }
   }
-  // 添加一个矩形，返回所在的节点id
-  pub fn add(&mut self, size: Vector2<usize>) -> usize {
-    match find_node(&self.slab, &size, 1) {
-      Some((id, area, pos)) => {
-        // 计算对齐大小
-        let mut asize = size.clone();
-        align_size(self.align, &mut asize);
-        // 创建右子节点
-        let right = if area.x > asize.x {
-          self.slab.insert(Node::new(
-            Vector2 {
-              x: area.x - asize.x,
-              y: asize.y,
-            },
-            Point2 {
-              x: pos.x + asize.x,
-              y: pos.y,
-            },
-            id,
-            ContentChild::Empty,
-          ))
-        } else {
-          0
-        };
-        // 创建下子节点
-        let bottom = if area.y > asize.y {
-          self.slab.insert(Node::new(
-            Vector2 {
-              x: area.x,
-              y: area.y - asize.y,
-            },
-            Point2 {
-              x: pos.x,
-              y: pos.y + asize.y,
-            },
-            id,
-            ContentChild::Empty,
-          ))
-        } else {
-          0
-        };
-        let (newid, content) = if id > 1 && right == 0 && bottom == 0 {
-          // 放入指定的大小，该节点仅能放入该size。如果是根节点，则必须放到其左子节点
-          (id, ContentChild::Content(size))
-        } else {
-          // 放入指定的大小，劈分该节点， 创建左子节点，左子节点仅能放入该size
-          let left = self
-            .slab
-            .insert(Node::new(asize, pos, id, ContentChild::Content(size)));
-          (left, ContentChild::Child(left, right, bottom, 1))
-        };
-        let node = unsafe { self.slab.get_unchecked_mut(id) };
-        node.content = content;
-        let p = node.parent;
-        incr_count(&mut self.slab, p);
-        newid
+  // 遍历所有的矩形，返回(id, 大小, 位置)，大小和位置同get
+  pub fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (usize, &'a Vector2<usize>, &'a Point2<usize>)> + 'a> {
+    Box::new(self.slab.iter().filter_map(|(id, node)| match node.content {
+      ContentChild::Content(ref size, ref pos, _) => Some((id, size, pos)),
+      _ => None,
+    }))
+  }
+  // 已占用的面积，包括边距及对齐的部分
+  pub fn used_area(&self) -> usize {
+    self.slab.iter().fold(0, |a, (_, node)| match node.content {
+      ContentChild::Content(..) => a + node.size.x * node.size.y,
+      _ => a,
+    })
+  }
+  // 空闲的面积
+  pub fn free_area(&self) -> usize {
+    let size = self.get_size();
+    size.x * size.y - self.used_area()
+  }
+  // 面积最大的空闲节点的大小，不超过该大小(包括边距)的矩形一定能放入，没有空闲节点返回0
+  pub fn max_free_rect(&self) -> Vector2<usize> {
+    let mut r = Vector2 { x: 0, y: 0 };
+    for (_, node) in self.slab.iter() {
+      if let ContentChild::Empty = node.content {
+        if node.size.x * node.size.y > r.x * r.y {
+          r = node.size;
+        }
       }
+    }
+    r
+  }
+  // 碎片率，为1减去最大空闲节点面积占空闲面积的比例，0表示空闲区域是一整块，越接近1越零碎
+  pub fn fragmentation(&self) -> f32 {
+    let free = self.free_area();
+    if free == 0 {
+      return 0.0;
+    }
+    let max = self.max_free_rect();
+    1.0 - (max.x * max.y) as f32 / free as f32
+  }
+  // 添加一个矩形，使用默认的边距，返回所在的节点id
+  pub fn add(&mut self, size: Vector2<usize>) -> usize {
+    let padding = self.padding;
+    self.add_with_padding(size, padding)
+  }
+  // 添加一个矩形，四周留出指定的边距，返回所在的节点id
+  pub fn add_with_padding(&mut self, size: Vector2<usize>, padding: usize) -> usize {
+    match fit_node(&self.slab, &size, padding, self.rotate) {
+      Some((id, area, pos, rotated)) => self.place(id, area, pos, size, padding, rotated),
       _ => 0,
     }
   }
-  // 获取指定id矩形的大小和位置
+  // 在指定的空节点中放入矩形，劈分出右子节点和下子节点，返回所在的节点id
+  fn place(
+    &mut self,
+    id: usize,
+    area: Vector2<usize>,
+    pos: Point2<usize>,
+    size: Vector2<usize>,
+    padding: usize,
+    rotated: bool,
+  ) -> usize {
+    // 计算对齐大小
+    let mut asize = if rotated {
+      Vector2 {
+        x: size.y + padding * 2,
+        y: size.x + padding * 2,
+      }
+    } else {
+      Vector2 {
+        x: size.x + padding * 2,
+        y: size.y + padding * 2,
+      }
+    };
+    align_size(self.align, &mut asize);
+    let inner = Point2 {
+      x: pos.x + padding,
+      y: pos.y + padding,
+    };
+    // 创建右子节点
+    let right = if area.x > asize.x {
+      self.slab.insert(Node::new(
+        Vector2 {
+          x: area.x - asize.x,
+          y: asize.y,
+        },
+        Point2 {
+          x: pos.x + asize.x,
+          y: pos.y,
+        },
+        id,
+        ContentChild::Empty,
+      ))
+    } else {
+      0
+    };
+    // 创建下子节点
+    let bottom = if area.y > asize.y {
+      self.slab.insert(Node::new(
+        Vector2 {
+          x: area.x,
+          y: area.y - asize.y,
+        },
+        Point2 {
+          x: pos.x,
+          y: pos.y + asize.y,
+        },
+        id,
+        ContentChild::Empty,
+      ))
+    } else {
+      0
+    };
+    let (newid, content) = if id > 1 && right == 0 && bottom == 0 {
+      // 放入指定的大小，该节点仅能放入该size。如果是根节点，则必须放到其左子节点
+      (id, ContentChild::Content(size, inner, rotated))
+    } else {
+      // 放入指定的大小，劈分该节点， 创建左子节点，左子节点仅能放入该size
+      let left = self
+        .slab
+        .insert(Node::new(asize, pos, id, ContentChild::Content(size, inner, rotated)));
+      (left, ContentChild::Child(left, right, bottom, 1))
+    };
+    let node = unsafe { self.slab.get_unchecked_mut(id) };
+    node.content = content;
+    let p = node.parent;
+    incr_count(&mut self.slab, p);
+    newid
+  }
+  // 添加一个矩形，使用默认的边距，返回所在的节点id及扩大后的新大小(没有扩大为None)
+  pub fn try_add(&mut self, size: Vector2<usize>) -> Result<(usize, Option<Vector2<usize>>), AddError> {
+    let padding = self.padding;
+    self.try_add_with_padding(size, padding)
+  }
+  // 添加一个矩形，四周留出指定的边距。放不下时按自动扩大的策略扩大总大小，直到放下或达到最大大小
+  // 返回所在的节点id及扩大后的新大小(没有扩大为None)，扩大后需要按新大小重新分配纹理
+  // 扩大到最大大小后因碎片仍放不下时，扩大不会撤销，新大小由AddError::Full返回
+  pub fn try_add_with_padding(
+    &mut self,
+    size: Vector2<usize>,
+    padding: usize,
+  ) -> Result<(usize, Option<Vector2<usize>>), AddError> {
+    let mut outer = Vector2 {
+      x: size.x + padding * 2,
+      y: size.y + padding * 2,
+    };
+    align_size(self.align, &mut outer);
+    let limit = match self.grow {
+      Grow::None => self.get_size().clone(),
+      _ => self.max_size,
+    };
+    let fit = |w: usize, h: usize| w <= limit.x && h <= limit.y;
+    if !fit(outer.x, outer.y) && !(self.rotate && fit(outer.y, outer.x)) {
+      return Err(AddError::TooLarge);
+    }
+    let mut grown = None;
+    loop {
+      let id = self.add_with_padding(size, padding);
+      if id > 0 {
+        return Ok((id, grown));
+      }
+      let old = self.get_size().clone();
+      let next = self.grow.next(&old, &self.max_size);
+      if next == old {
+        return Err(AddError::Full(grown));
+      }
+      self.extends(next);
+      grown = Some(self.get_size().clone());
+    }
+  }
+  // 获取指定id矩形添加时的大小和位置，不包括边距。旋转放入的矩形实际占用的宽高是互换的，用get_info获取
   pub fn get(&self, id: usize) -> Option<(&Vector2<usize>, &Point2<usize>)> {
     match self.slab.get(id) {
       Some(node) => match node.content {
-        ContentChild::Content(ref size) => Some((size, &node.pos)),
+        ContentChild::Content(ref size, ref pos, _) => Some((size, pos)),
+        _ => None,
+      },
+      _ => None,
+    }
+  }
+  // 获取指定id矩形的详细信息
+  pub fn get_info(&self, id: usize) -> Option<RectInfo> {
+    match self.slab.get(id) {
+      Some(node) => match node.content {
+        ContentChild::Content(size, pos, rotated) => Some(RectInfo {
+          size: size,
+          pos: pos,
+          padding: pos.x - node.pos.x,
+          rotated: rotated,
+        }),
         _ => None,
       },
       _ => None,
     }
   }
-  // 移除指定id的矩形
+  // 获取指定id矩形的边距
+  pub fn get_rect_padding(&self, id: usize) -> Option<usize> {
+    self.get_info(id).map(|info| info.padding)
+  }
+  // 获取指定id矩形的边距需要填充的区域
+  pub fn get_extrusion(&self, id: usize) -> Vec<Extrusion> {
+    match self.get_info(id) {
+      Some(info) => extrusion(&info.placed_size(), &info.pos, info.padding),
+      _ => Vec::new(),
+    }
+  }
+  // 移除指定id的矩形，返回矩形的大小和位置，不包括边距
   pub fn remove(&mut self, id: usize) -> (Vector2<usize>, Point2<usize>) {
     let node = unsafe { self.slab.get_unchecked_mut(id) };
-    let size = match node.content {
-      ContentChild::Content(size) => size,
+    let (size, pos) = match node.content {
+      ContentChild::Content(size, pos, _) => (size, pos),
       _ => panic!("invalid content"),
     };
     node.content = ContentChild::Empty;
-    let pos = node.pos;
     let p = node.parent;
     attempt_defrag(&mut self.slab, p);
     (size, pos)
@@ -213,6 +417,401 @@ impl RectMap {
     root.content = ContentChild::Child(left, right, bottom, c);
     true
   }
+  // 重新紧凑地放置所有矩形，矩形的id、边距及旋转都保持不变，返回位置发生变化的矩形列表
+  // 按对齐后的高、宽从大到小依次放入，如果重新放置时放不下，则不做任何修改并返回None
+  // 拷贝像素时应先复制原纹理，再按列表从原位置拷贝到新位置
+  pub fn repack(&mut self) -> Option<Vec<Relocation>> {
+    let size = self.get_size().clone();
+    self.resize(size)
+  }
+  // 修改总大小并重新紧凑地放置所有矩形，返回位置发生变化的矩形列表，放不下则不做任何修改并返回None
+  // 只扩大时同extends，矩形不移动
+  pub fn try_resize(&mut self, mut size: Vector2<usize>) -> Option<Vec<Relocation>> {
+    align_size(self.align, &mut size);
+    let old = self.get_size().clone();
+    if size.x >= old.x && size.y >= old.y {
+      self.extends(size);
+      return Some(Vec::new());
+    }
+    self.resize(size)
+  }
+  // 缩小到能放下所有矩形的最小对齐大小(面积最小，其次宽高最接近，再其次较窄)，返回位置发生变化的矩形列表
+  // 找不到更小的大小时不做任何修改，没有矩形时缩小到一个对齐大小
+  pub fn shrink_to_fit(&mut self) -> Vec<Relocation> {
+    let a = self.align;
+    let old = self.get_size().clone();
+    let list = self.live_rects();
+    let (mut minw, mut minh, mut area) = (a, a, 0);
+    for &(_, _, ref s) in list.iter() {
+      minw = if s.x > minw { s.x } else { minw };
+      minh = if s.y > minh { s.y } else { minh };
+      area += s.x * s.y;
+    }
+    // 面积更小，其次宽高更接近
+    let smaller = |w: usize, h: usize, s: &Vector2<usize>| {
+      let d = |x: usize, y: usize| if x > y { x - y } else { y - x };
+      w * h < s.x * s.y || (w * h == s.x * s.y && d(w, h) < d(s.x, s.y))
+    };
+    // 依次尝试每个宽度，二分查找能放下的最小高度
+    let mut best_size = old;
+    let mut best = None;
+    let mut w = minw;
+    while w <= old.x {
+      let mut lo = (area + w - 1) / w;
+      lo = if lo > minh { lo } else { minh };
+      lo = (lo + a - 1) / a * a;
+      if lo <= old.y && smaller(w, lo, &best_size) {
+        let (mut l, mut r) = (lo / a, old.y / a);
+        let mut found = None;
+        while l <= r {
+          let m = (l + r) / 2;
+          match place_all(&list, Vector2 { x: w, y: m * a }, a) {
+            Some(p) => {
+              found = Some((m * a, p));
+              r = m - 1;
+            }
+            _ => l = m + 1,
+          }
+        }
+        if let Some((h, p)) = found {
+          if smaller(w, h, &best_size) {
+            best_size = Vector2 { x: w, y: h };
+            best = Some(p);
+          }
+        }
+      }
+      w += a;
+    }
+    match best {
+      Some((map, placed)) if best_size.x * best_size.y < old.x * old.y => self.apply(map, &list, &placed),
+      _ => Vec::new(),
+    }
+  }
+  // 离线批量装箱，已知所有矩形的大小时使用，结果和矩形的顺序无关
+  // 矩形按对齐后的长边、面积及原大小从大到小排序，依次放入面积最小、短边剩余最少的空节点
+  // bin为Fixed时使用max_size，否则在不超过max_size的大小中查找最小的正方形或2的幂，max_size先向下对齐
+  // 返回新的矩形表(默认边距及是否旋转同参数)，及按输入顺序每个矩形的id和信息，放不下返回None
+  pub fn pack(
+    sizes: &[Vector2<usize>],
+    mut max_size: Vector2<usize>,
+    align: usize,
+    padding: usize,
+    rotate: bool,
+    bin: Bin,
+  ) -> Option<(RectMap, Vec<(usize, RectInfo)>)> {
+    max_size.x -= max_size.x % align;
+    max_size.y -= max_size.y % align;
+    let outers: Vec<Vector2<usize>> = sizes
+      .iter()
+      .map(|s| {
+        let mut o = Vector2 {
+          x: s.x + padding * 2,
+          y: s.y + padding * 2,
+        };
+        align_size(align, &mut o);
+        o
+      })
+      .collect();
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by(|a, b| {
+      let key = |i: usize| {
+        let (o, s) = (&outers[i], &sizes[i]);
+        (if o.x > o.y { o.x } else { o.y }, o.x * o.y, s.y, s.x)
+      };
+      key(*b).cmp(&key(*a)).then(a.cmp(b))
+    });
+    let area = outers.iter().fold(0, |a, o| a + o.x * o.y);
+    let pack = |size: Vector2<usize>| pack_all(sizes, &order, size, align, padding, rotate);
+    let r = match bin {
+      Bin::Fixed => pack(max_size),
+      Bin::Square => {
+        // 边长的下限为面积的平方根及每个矩形的长边，旋转也不能让长边变短，至少为一个对齐大小
+        let mut lo = (area as f64).sqrt().ceil() as usize;
+        for o in outers.iter() {
+          let long = if o.x > o.y { o.x } else { o.y };
+          lo = if long > lo { long } else { lo };
+        }
+        let hi = if max_size.x < max_size.y { max_size.x } else { max_size.y };
+        let (mut l, mut h) = ((lo + align - 1) / align, hi / align);
+        if l == 0 {
+          l = 1;
+        }
+        let mut found = None;
+        // l不小于1，所以m - 1不会溢出
+        while l <= h {
+          let m = (l + h) / 2;
+          match pack(Vector2 { x: m * align, y: m * align }) {
+            Some(r) => {
+              found = Some(r);
+              h = m - 1;
+            }
+            _ => l = m + 1,
+          }
+        }
+        found
+      }
+      Bin::PowerOfTwo => {
+        let pows = |max: usize| {
+          let mut v = Vec::new();
+          let mut p = 1;
+          while p <= max {
+            if p % align == 0 {
+              v.push(p);
+            }
+            p *= 2;
+          }
+          v
+        };
+        let mut list = Vec::new();
+        for w in pows(max_size.x) {
+          for h in pows(max_size.y) {
+            if w * h >= area {
+              list.push(Vector2 { x: w, y: h });
+            }
+          }
+        }
+        // 面积最小，其次宽高最接近，再其次较宽
+        list.sort_by_key(|s| (s.x * s.y, if s.x > s.y { s.x / s.y } else { s.y / s.x }, usize::max_value() - s.x));
+        list.into_iter().filter_map(pack).next()
+      }
+    };
+    r.map(|(mut map, ids)| {
+      map.set_padding(padding);
+      map.set_rotate(rotate);
+      let infos = ids.iter().map(|id| (*id, map.get_info(*id).unwrap())).collect();
+      (map, infos)
+    })
+  }
+  // 在指定大小的新矩形表中重新放置所有矩形
+  fn resize(&mut self, size: Vector2<usize>) -> Option<Vec<Relocation>> {
+    let list = self.live_rects();
+    match place_all(&list, size, self.align) {
+      Some((map, placed)) => Some(self.apply(map, &list, &placed)),
+      _ => None,
+    }
+  }
+  // 所有矩形的id、信息及占用的节点大小，按节点的高、宽从大到小排列
+  fn live_rects(&self) -> Vec<(usize, RectInfo, Vector2<usize>)> {
+    let mut list = Vec::new();
+    for (id, node) in self.slab.iter() {
+      if let ContentChild::Content(size, pos, rotated) = node.content {
+        let padding = pos.x - node.pos.x;
+        let info = RectInfo {
+          size: size,
+          pos: pos,
+          padding: padding,
+          rotated: rotated,
+        };
+        list.push((id, info, node.size.clone()));
+      }
+    }
+    list.sort_by(|a, b| (b.2.y, b.2.x, a.0).cmp(&(a.2.y, a.2.x, b.0)));
+    list
+  }
+  // 用place_all放置好的矩形表替换当前的节点，放矩形的节点保持原id，返回位置发生变化的矩形列表
+  fn apply(
+    &mut self,
+    map: RectMap,
+    list: &[(usize, RectInfo, Vector2<usize>)],
+    placed: &[(usize, Point2<usize>)],
+  ) -> Vec<Relocation> {
+    // 新节点id到最终id的映射，放矩形的节点使用原id，其余节点使用未被占用的id
+    let len = map.slab.len();
+    let total = list.iter().fold(len, |t, r| if r.0 > t { r.0 } else { t });
+    let mut remap = vec![0; len + 1];
+    let mut used = vec![false; total + 1];
+    remap[1] = 1;
+    used[1] = true;
+    for (r, &(id, _)) in list.iter().zip(placed.iter()) {
+      remap[id] = r.0;
+      used[r.0] = true;
+    }
+    let mut free = (2..total + 1).filter(|i| !used[*i]);
+    for i in 2..len + 1 {
+      if remap[i] == 0 {
+        remap[i] = free.next().unwrap();
+      }
+    }
+    // 新建的slab依次分配1..total的id，先占位，再填入节点，最后释放未使用的id
+    let mut slab = Slab::new();
+    for _ in 0..total {
+      slab.insert(Node::new(
+        Vector2 { x: 0, y: 0 },
+        Point2 { x: 0, y: 0 },
+        0,
+        ContentChild::Empty,
+      ));
+    }
+    let mut filled = vec![false; total + 1];
+    for (id, node) in map.slab.iter() {
+      let mut node = node.clone();
+      node.parent = remap[node.parent];
+      if let ContentChild::Child(l, r, b, c) = node.content {
+        node.content = ContentChild::Child(remap[l], remap[r], remap[b], c);
+      }
+      *unsafe { slab.get_unchecked_mut(remap[id]) } = node;
+      filled[remap[id]] = true;
+    }
+    for i in 1..total + 1 {
+      if !filled[i] {
+        slab.remove(i);
+      }
+    }
+    self.slab = slab;
+    let mut moves = Vec::new();
+    for (r, &(_, pos)) in list.iter().zip(placed.iter()) {
+      if r.1.pos != pos {
+        moves.push(Relocation {
+          id: r.0,
+          size: r.1.placed_size(),
+          old_pos: r.1.pos,
+          new_pos: pos,
+        });
+      }
+    }
+    moves
+  }
+}
+
+/// 多页的图集，每页是一个固定大小的矩形表，放不下时自动创建新页，页为空时释放
+///
+/// 返回的(页, 页内id)没有代数，和RectMap的id一样会被复用：页释放后页号会分配给新页，
+/// 矩形移除后页内id会分配给新矩形。调用者必须在移除后丢弃旧的(页, 页内id)，否则会指向别的矩形
+pub struct AtlasSet {
+  pages: Slab<RectMap>,
+  size: Vector2<usize>, // 每页的大小
+  align: usize,         // 对齐大小
+  padding: usize,       // 默认的边距
+  rotate: bool,         // 是否允许旋转放入
+}
+
+impl AtlasSet {
+  pub fn new(size: Vector2<usize>, align: usize) -> Self {
+    AtlasSet {
+      pages: Slab::new(),
+      size: size,
+      align: align,
+      padding: 0,
+      rotate: false,
+    }
+  }
+  // 获得每页的大小
+  pub fn get_size(&self) -> &Vector2<usize> {
+    &self.size
+  }
+  // 获得对齐大小
+  pub fn get_align(&self) -> usize {
+    self.align
+  }
+  // 获得默认的边距
+  pub fn get_padding(&self) -> usize {
+    self.padding
+  }
+  // 设置默认的边距，只影响之后添加的矩形
+  pub fn set_padding(&mut self, padding: usize) {
+    self.padding = padding;
+  }
+  // 是否允许旋转放入
+  pub fn get_rotate(&self) -> bool {
+    self.rotate
+  }
+  // 设置是否允许旋转放入，只影响之后添加的矩形
+  pub fn set_rotate(&mut self, rotate: bool) {
+    self.rotate = rotate;
+  }
+  // 获得页数
+  pub fn page_count(&self) -> usize {
+    self.pages.len()
+  }
+  // 获得指定页
+  pub fn get_page(&self, page: usize) -> Option<&RectMap> {
+    self.pages.get(page)
+  }
+  // 遍历所有的页
+  pub fn pages<'a>(&'a self) -> Box<dyn Iterator<Item = (usize, &'a RectMap)> + 'a> {
+    Box::new(self.pages.iter())
+  }
+  // 获取总的矩形数量
+  pub fn count(&self) -> usize {
+    self.pages.iter().map(|(_, map)| map.count()).sum()
+  }
+  // 添加一个矩形，使用默认的边距，返回(页, 页内id)
+  pub fn add(&mut self, size: Vector2<usize>) -> Option<(usize, usize)> {
+    let padding = self.padding;
+    self.add_with_padding(size, padding)
+  }
+  // 添加一个矩形，四周留出指定的边距，返回(页, 页内id)。依次尝试已有的页，都放不下则创建新页
+  // 比页还大的矩形返回None
+  pub fn add_with_padding(&mut self, size: Vector2<usize>, padding: usize) -> Option<(usize, usize)> {
+    let mut page = 0;
+    for (i, map) in self.pages.iter() {
+      if fit_node(&map.slab, &size, padding, self.rotate).is_some() {
+        page = i;
+        break;
+      }
+    }
+    if page == 0 {
+      let mut map = RectMap::new(self.size, self.align);
+      map.set_padding(self.padding);
+      map.set_rotate(self.rotate);
+      let id = map.add_with_padding(size, padding);
+      if id == 0 {
+        return None;
+      }
+      return Some((self.pages.insert(map), id));
+    }
+    let map = unsafe { self.pages.get_unchecked_mut(page) };
+    map.set_rotate(self.rotate);
+    Some((page, map.add_with_padding(size, padding)))
+  }
+  // 获取指定矩形的大小和位置，不包括边距
+  pub fn get(&self, page: usize, id: usize) -> Option<(&Vector2<usize>, &Point2<usize>)> {
+    match self.pages.get(page) {
+      Some(map) => map.get(id),
+      _ => None,
+    }
+  }
+  // 移除指定的矩形，返回矩形的大小和位置。如果页变空，则释放该页，get_page将返回None，之后新建的页可能复用该页号
+  pub fn remove(&mut self, page: usize, id: usize) -> Option<(Vector2<usize>, Point2<usize>)> {
+    let (r, empty) = match self.pages.get_mut(page) {
+      Some(map) => {
+        if map.get(id).is_none() {
+          return None;
+        }
+        let r = map.remove(id);
+        (r, map.count() == 0)
+      }
+      _ => return None,
+    };
+    if empty {
+      self.pages.remove(page);
+    }
+    Some(r)
+  }
+}
+
+impl Atlas for RectMap {
+  fn get_size(&self) -> &Vector2<usize> {
+    RectMap::get_size(self)
+  }
+  fn get_align(&self) -> usize {
+    self.align
+  }
+  fn count(&self) -> usize {
+    RectMap::count(self)
+  }
+  fn add(&mut self, size: Vector2<usize>) -> usize {
+    RectMap::add(self, size)
+  }
+  fn get(&self, id: usize) -> Option<(&Vector2<usize>, &Point2<usize>)> {
+    RectMap::get(self, id)
+  }
+  fn remove(&mut self, id: usize) -> (Vector2<usize>, Point2<usize>) {
+    RectMap::remove(self, id)
+  }
+  fn extends(&mut self, size: Vector2<usize>) -> bool {
+    RectMap::extends(self, size)
+  }
 }
 
 #[derive(Debug, Clone)]
@@ -226,7 +825,7 @@ struct Node {
 #[derive(Debug, Clone)]
 enum ContentChild {
   Empty,                             // 空
-  Content(Vector2<usize>),           // 内容大小
+  Content(Vector2<usize>, Point2<usize>, bool), // 内容大小，内容位置(不包括边距)，是否旋转
   Child(usize, usize, usize, usize), // 左子节点, 右子节点, 下子节点，包含的矩形总数量
 }
 
@@ -242,6 +841,276 @@ impl Node {
   }
 }
 
+/// 矩形的详细信息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RectInfo {
+  pub size: Vector2<usize>, // 添加时的大小
+  pub pos: Point2<usize>,   // 位置，不包括边距
+  pub padding: usize,       // 边距
+  pub rotated: bool,        // 是否旋转90度放入，旋转后占用的宽高互换
+}
+
+impl RectInfo {
+  // 实际占用的大小，不包括边距
+  pub fn placed_size(&self) -> Vector2<usize> {
+    if self.rotated {
+      Vector2 {
+        x: self.size.y,
+        y: self.size.x,
+      }
+    } else {
+      self.size
+    }
+  }
+}
+
+/// 自动扩大的策略，每次扩大较短的边，较短的边达到最大大小后扩大另一条边
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grow {
+  None,        // 不自动扩大
+  PowerOfTwo,  // 扩大到下一个2的幂
+  Step(usize), // 每次扩大固定的步长
+}
+
+impl Grow {
+  // 计算下一次扩大后的大小，不能再扩大返回原大小
+  fn next(&self, size: &Vector2<usize>, max: &Vector2<usize>) -> Vector2<usize> {
+    let incr = |v: usize, m: usize| {
+      let v = match *self {
+        Grow::None => v,
+        Grow::PowerOfTwo => (v + 1).next_power_of_two(),
+        Grow::Step(step) => v + step,
+      };
+      if v > m {
+        m
+      } else {
+        v
+      }
+    };
+    let (w, h) = (size.x, size.y);
+    if w <= h {
+      let x = incr(w, max.x);
+      if x > w {
+        return Vector2 { x: x, y: h };
+      }
+      let y = incr(h, max.y);
+      Vector2 { x: w, y: if y > h { y } else { h } }
+    } else {
+      let y = incr(h, max.y);
+      if y > h {
+        return Vector2 { x: w, y: y };
+      }
+      let x = incr(w, max.x);
+      Vector2 { x: if x > w { x } else { w }, y: h }
+    }
+  }
+}
+
+/// 批量装箱时总大小的选择方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bin {
+  Fixed,      // 使用指定的大小
+  Square,     // 能放下所有矩形的最小正方形
+  PowerOfTwo, // 能放下所有矩形、宽高都是2的幂的最小大小
+}
+
+/// try_add的错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddError {
+  TooLarge, // 矩形比总大小(允许自动扩大时为最大大小)还大，永远放不下
+  // 剩余空间放不下，且已不能再扩大。本次调用扩大过总大小时为扩大后的新大小，仍需按新大小重新分配纹理
+  Full(Option<Vector2<usize>>),
+}
+
+impl fmt::Display for AddError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      AddError::TooLarge => write!(f, "rect is larger than the max size"),
+      AddError::Full(_) => write!(f, "no space left for the rect"),
+    }
+  }
+}
+
+impl Error for AddError {}
+
+/// 重新放置后位置发生变化的矩形，位置和大小都不包括边距，边距需要用get_extrusion重新填充
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+  pub id: usize,              // 矩形的id
+  pub size: Vector2<usize>,   // 实际占用的大小，旋转放入的矩形宽高互换
+  pub old_pos: Point2<usize>, // 原位置
+  pub new_pos: Point2<usize>, // 新位置
+}
+
+/// 边距的填充区域，将src矩形的像素拉伸到dst矩形上
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extrusion {
+  pub src_pos: Point2<usize>,
+  pub src_size: Vector2<usize>,
+  pub dst_pos: Point2<usize>,
+  pub dst_size: Vector2<usize>,
+}
+
+// 计算矩形四周边距的填充区域，依次为上、下、左、右四条边和左上、右上、左下、右下四个角
+// 边用矩形最外一行或一列的像素填充，角用矩形角上的像素填充
+pub fn extrusion(size: &Vector2<usize>, pos: &Point2<usize>, padding: usize) -> Vec<Extrusion> {
+  if padding == 0 || size.x == 0 || size.y == 0 {
+    return Vec::new();
+  }
+  let (x, y, w, h, p) = (pos.x, pos.y, size.x, size.y, padding);
+  let e = |sx, sy, sw, sh, dx, dy, dw, dh| Extrusion {
+    src_pos: Point2 { x: sx, y: sy },
+    src_size: Vector2 { x: sw, y: sh },
+    dst_pos: Point2 { x: dx, y: dy },
+    dst_size: Vector2 { x: dw, y: dh },
+  };
+  vec![
+    e(x, y, w, 1, x, y - p, w, p),
+    e(x, y + h - 1, w, 1, x, y + h, w, p),
+    e(x, y, 1, h, x - p, y, p, h),
+    e(x + w - 1, y, 1, h, x + w, y, p, h),
+    e(x, y, 1, 1, x - p, y - p, p, p),
+    e(x + w - 1, y, 1, 1, x + w, y - p, p, p),
+    e(x, y + h - 1, 1, 1, x - p, y + h, p, p),
+    e(x + w - 1, y + h - 1, 1, 1, x + w, y + h, p, p),
+  ]
+}
+
+// 空闲或已占用的矩形区域
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rect {
+  x: usize,
+  y: usize,
+  w: usize,
+  h: usize,
+}
+
+impl Rect {
+  #[inline]
+  fn new(x: usize, y: usize, w: usize, h: usize) -> Rect {
+    Rect { x: x, y: y, w: w, h: h }
+  }
+  #[inline]
+  fn right(&self) -> usize {
+    self.x + self.w
+  }
+  #[inline]
+  fn bottom(&self) -> usize {
+    self.y + self.h
+  }
+  // 是否包含另一个矩形
+  #[inline]
+  fn contains(&self, r: &Rect) -> bool {
+    r.x >= self.x && r.y >= self.y && r.right() <= self.right() && r.bottom() <= self.bottom()
+  }
+  // 是否和另一个矩形相交
+  #[inline]
+  fn intersects(&self, r: &Rect) -> bool {
+    r.x < self.right() && r.right() > self.x && r.y < self.bottom() && r.bottom() > self.y
+  }
+  // 和另一个矩形接触的边长
+  fn contact(&self, r: &Rect) -> usize {
+    let mut c = 0;
+    if self.x == r.right() || self.right() == r.x {
+      c += common(self.y, self.bottom(), r.y, r.bottom());
+    }
+    if self.y == r.bottom() || self.bottom() == r.y {
+      c += common(self.x, self.right(), r.x, r.right());
+    }
+    c
+  }
+  // 两个矩形的并集正好是矩形时，返回并集
+  fn merge(&self, r: &Rect) -> Option<Rect> {
+    if self.x == r.x && self.w == r.w && self.y <= r.bottom() && r.y <= self.bottom() {
+      let y = if self.y < r.y { self.y } else { r.y };
+      let b = if self.bottom() > r.bottom() { self.bottom() } else { r.bottom() };
+      return Some(Rect::new(self.x, y, self.w, b - y));
+    }
+    if self.y == r.y && self.h == r.h && self.x <= r.right() && r.x <= self.right() {
+      let x = if self.x < r.x { self.x } else { r.x };
+      let right = if self.right() > r.right() { self.right() } else { r.right() };
+      return Some(Rect::new(x, self.y, right - x, self.h));
+    }
+    None
+  }
+}
+
+// 两个区间重叠的长度
+#[inline]
+fn common(a1: usize, a2: usize, b1: usize, b2: usize) -> usize {
+  let l = if a1 > b1 { a1 } else { b1 };
+  let r = if a2 < b2 { a2 } else { b2 };
+  if r > l {
+    r - l
+  } else {
+    0
+  }
+}
+
+// 合并矩形列表中能合并的矩形，直到不能合并为止
+fn merge_rects(list: &mut Vec<Rect>) {
+  let mut i = 0;
+  while i < list.len() {
+    let mut j = i + 1;
+    let mut merged = false;
+    while j < list.len() {
+      match list[i].merge(&list[j]) {
+        Some(r) => {
+          list[i] = r;
+          list.swap_remove(j);
+          merged = true;
+        }
+        _ => j += 1,
+      }
+    }
+    // 合并后变大的矩形可能可以和前面的矩形合并，需要重新检查
+    i = if merged { 0 } else { i + 1 };
+  }
+}
+
+// 去掉被其他矩形包含的矩形
+fn prune_rects(list: &mut Vec<Rect>) {
+  let mut i = 0;
+  while i < list.len() {
+    let mut contained = false;
+    for j in 0..list.len() {
+      if i != j && list[j].contains(&list[i]) {
+        contained = true;
+        break;
+      }
+    }
+    if contained {
+      list.swap_remove(i);
+    } else {
+      i += 1;
+    }
+  }
+}
+
+// 在指定大小的新矩形表中按原方向依次放入矩形，返回新的矩形表及每个矩形的新节点id和新位置，放不下返回None
+fn place_all(
+  list: &[(usize, RectInfo, Vector2<usize>)],
+  size: Vector2<usize>,
+  align: usize,
+) -> Option<(RectMap, Vec<(usize, Point2<usize>)>)> {
+  let mut map = RectMap::new(size, align);
+  let mut placed = Vec::with_capacity(list.len());
+  for &(_, ref info, _) in list.iter() {
+    let id = map.add_with_padding(info.placed_size(), info.padding);
+    if id == 0 {
+      return None;
+    }
+    let node = unsafe { map.slab.get_unchecked_mut(id) };
+    let pos = match node.content {
+      ContentChild::Content(_, pos, _) => pos,
+      _ => panic!("invalid content"),
+    };
+    node.content = ContentChild::Content(info.size, pos, info.rotated);
+    placed.push((id, pos));
+  }
+  Some((map, placed))
+}
+
 // 对齐大小
 pub fn align_size(align: usize, size: &mut Vector2<usize>) {
   let a = size.x % align;
@@ -290,6 +1159,92 @@ fn find_node(
   }
 }
 
+// 寻找能放下指定大小及边距的节点，返回节点id、节点大小、节点位置及是否旋转
+// 允许旋转时，两个方向都能放下，则选择面积更小、短边剩余更少的节点
+fn fit_node(
+  slab: &Slab<Node>,
+  size: &Vector2<usize>,
+  padding: usize,
+  rotate: bool,
+) -> Option<(usize, Vector2<usize>, Point2<usize>, bool)> {
+  let outer = Vector2 {
+    x: size.x + padding * 2,
+    y: size.y + padding * 2,
+  };
+  let r = find_node(slab, &outer, 1).map(|(id, area, pos)| (id, area, pos, false));
+  if !rotate || size.x == size.y {
+    return r;
+  }
+  let t = find_node(slab, &Vector2 { x: outer.y, y: outer.x }, 1).map(|(id, area, pos)| (id, area, pos, true));
+  match (r, t) {
+    (Some(r), Some(t)) => {
+      let score = |area: &Vector2<usize>, w: usize, h: usize| {
+        let short = if area.x - w < area.y - h { area.x - w } else { area.y - h };
+        (area.x * area.y, short)
+      };
+      if score(&t.1, outer.y, outer.x) < score(&r.1, outer.x, outer.y) {
+        Some(t)
+      } else {
+        Some(r)
+      }
+    }
+    (None, t) => t,
+    (r, _) => r,
+  }
+}
+
+// 按顺序将矩形放入指定大小的新矩形表，每次选择面积最小、短边剩余最少的空节点
+// 返回新的矩形表及按输入顺序每个矩形的id，放不下返回None
+fn pack_all(
+  sizes: &[Vector2<usize>],
+  order: &[usize],
+  size: Vector2<usize>,
+  align: usize,
+  padding: usize,
+  rotate: bool,
+) -> Option<(RectMap, Vec<usize>)> {
+  let mut map = RectMap::new(size, align);
+  let mut ids = vec![0; sizes.len()];
+  for i in order.iter() {
+    let size = sizes[*i];
+    ids[*i] = match best_node(&map.slab, &size, padding, rotate) {
+      Some((id, area, pos, rotated)) => map.place(id, area, pos, size, padding, rotated),
+      _ => return None,
+    };
+  }
+  Some((map, ids))
+}
+
+// 在所有空节点中寻找能放下指定大小及边距、面积最小、短边剩余最少的节点，两个方向相同时不旋转
+fn best_node(
+  slab: &Slab<Node>,
+  size: &Vector2<usize>,
+  padding: usize,
+  rotate: bool,
+) -> Option<(usize, Vector2<usize>, Point2<usize>, bool)> {
+  let outer = Vector2 {
+    x: size.x + padding * 2,
+    y: size.y + padding * 2,
+  };
+  let mut best = None;
+  for (id, node) in slab.iter() {
+    if let ContentChild::Empty = node.content {
+      for &(w, h, rotated) in [(outer.x, outer.y, false), (outer.y, outer.x, true)].iter() {
+        if (rotated && (!rotate || size.x == size.y)) || w > node.size.x || h > node.size.y {
+          continue;
+        }
+        let (lw, lh) = (node.size.x - w, node.size.y - h);
+        let score = (node.size.x * node.size.y, if lw < lh { lw } else { lh }, rotated);
+        match best {
+          Some((s, _)) if s <= score => (),
+          _ => best = Some((score, (id, node.size, node.pos, rotated))),
+        }
+      }
+    }
+  }
+  best.map(|(_, r)| r)
+}
+
 #[inline]
 fn incr_count(slab: &mut Slab<Node>, id: usize) {
   if id == 0 {
@@ -355,3 +1310,451 @@ fn test11() {
   }
 
 }
+
+#[test]
+fn test_padding() {
+  let mut map = RectMap::new(Vector2{x:256, y:256}, 4);
+  map.set_padding(2);
+  let a = map.add(Vector2{x:10, y:10});
+  let b = map.add_with_padding(Vector2{x:10, y:10}, 0);
+  let c = map.add(Vector2{x:12, y:12});
+  assert_eq!(map.get(a), Some((&Vector2{x:10, y:10}, &Point2{x:2, y:2})));
+  assert_eq!(map.get_rect_padding(a), Some(2));
+  // a占用的区域为14x14，对齐到16x16
+  assert_eq!(map.get(b), Some((&Vector2{x:10, y:10}, &Point2{x:16, y:0})));
+  assert_eq!(map.get_rect_padding(b), Some(0));
+  assert_eq!(map.get_extrusion(b).len(), 0);
+  assert_eq!(map.get(c), Some((&Vector2{x:12, y:12}, &Point2{x:2, y:18})));
+  let e = map.get_extrusion(a);
+  assert_eq!(e.len(), 8);
+  assert_eq!(e[0], Extrusion {
+    src_pos: Point2{x:2, y:2}, src_size: Vector2{x:10, y:1},
+    dst_pos: Point2{x:2, y:0}, dst_size: Vector2{x:10, y:2},
+  });
+  assert_eq!(e[7], Extrusion {
+    src_pos: Point2{x:11, y:11}, src_size: Vector2{x:1, y:1},
+    dst_pos: Point2{x:12, y:12}, dst_size: Vector2{x:2, y:2},
+  });
+  // 填充区域加上内部矩形正好是占用的区域
+  let area: usize = e.iter().map(|e| e.dst_size.x * e.dst_size.y).sum();
+  assert_eq!(area + 100, 14 * 14);
+  assert_eq!(map.remove(a), (Vector2{x:10, y:10}, Point2{x:2, y:2}));
+  assert_eq!(map.count(), 2);
+}
+
+#[test]
+fn test_atlas_set() {
+  let mut set = AtlasSet::new(Vector2{x:64, y:64}, 16);
+  let mut list = Vec::new();
+  for _ in 0..6 {
+    list.push(set.add(Vector2{x:32, y:32}).unwrap());
+  }
+  // 每页放4个
+  assert_eq!(set.page_count(), 2);
+  assert_eq!(list[3].0, list[0].0);
+  assert_eq!(list[4].0, list[5].0);
+  assert!(list[4].0 != list[0].0);
+  assert_eq!(set.count(), 6);
+  assert!(set.add(Vector2{x:65, y:10}).is_none());
+  assert_eq!(set.page_count(), 2);
+  assert_eq!(set.get(list[5].0, list[5].1).map(|(s, _)| s.clone()), Some(Vector2{x:32, y:32}));
+  // 第一页空出位置后，优先放到第一页
+  set.remove(list[1].0, list[1].1).unwrap();
+  assert!(set.remove(list[1].0, list[1].1).is_none());
+  let r = set.add(Vector2{x:20, y:20}).unwrap();
+  assert_eq!(r.0, list[0].0);
+  // 页变空后释放
+  let page = list[4].0;
+  set.remove(page, list[4].1);
+  set.remove(page, list[5].1);
+  assert!(set.get_page(page).is_none());
+  assert_eq!(set.page_count(), 1);
+  assert_eq!(set.pages().count(), 1);
+  // 边距
+  set.set_padding(1);
+  let r = set.add(Vector2{x:62, y:62}).unwrap();
+  assert_eq!(set.get(r.0, r.1).map(|(_, p)| p.clone()), Some(Point2{x:1, y:1}));
+  assert_eq!(set.page_count(), 2);
+  // 释放的页号被新页复用
+  assert_eq!(r.0, page);
+}
+
+#[test]
+fn test_rotate() {
+  let mut map = RectMap::new(Vector2{x:64, y:64}, 1);
+  let a = map.add(Vector2{x:64, y:40});
+  // 剩余64x24，不旋转放不下
+  assert_eq!(map.add(Vector2{x:20, y:60}), 0);
+  map.set_rotate(true);
+  let b = map.add(Vector2{x:20, y:60});
+  assert!(b > 0);
+  assert_eq!(map.get(b), Some((&Vector2{x:20, y:60}, &Point2{x:0, y:40})));
+  let info = map.get_info(b).unwrap();
+  assert!(info.rotated);
+  assert_eq!(info.placed_size(), Vector2{x:60, y:20});
+  assert!(!map.get_info(a).unwrap().rotated);
+  // 剩余64x10，c只有旋转后才能放下；d两个方向都能放下，按更合适的方向不旋转
+  let mut map = RectMap::new(Vector2{x:64, y:64}, 1);
+  map.set_rotate(true);
+  map.add(Vector2{x:64, y:54});
+  let c = map.add(Vector2{x:8, y:50});
+  assert!(map.get_info(c).unwrap().rotated);
+  let d = map.add(Vector2{x:10, y:4});
+  assert!(!map.get_info(d).unwrap().rotated);
+  assert_eq!(map.remove(c), (Vector2{x:8, y:50}, Point2{x:0, y:54}));
+  // 旋转的矩形的边距按实际占用的大小计算
+  let mut map = RectMap::new(Vector2{x:64, y:64}, 1);
+  map.set_rotate(true);
+  map.set_padding(1);
+  assert!(map.add(Vector2{x:62, y:38}) > 0);
+  let e = map.add(Vector2{x:10, y:30});
+  assert_eq!(map.get(e), Some((&Vector2{x:10, y:30}, &Point2{x:1, y:41})));
+  let ext = map.get_extrusion(e);
+  assert_eq!(ext[1].dst_pos, Point2{x:1, y:51});
+  assert_eq!(ext[1].dst_size, Vector2{x:30, y:1});
+  // 多页图集
+  let mut set = AtlasSet::new(Vector2{x:64, y:64}, 1);
+  set.set_rotate(true);
+  let (p, _) = set.add(Vector2{x:64, y:40}).unwrap();
+  let (p2, id) = set.add(Vector2{x:20, y:60}).unwrap();
+  assert_eq!(p, p2);
+  assert!(set.get_page(p).unwrap().get_info(id).unwrap().rotated);
+}
+
+// 检查分配器中的矩形都在范围内且互不重叠
+#[cfg(test)]
+fn check_atlas<A: Atlas>(atlas: &A, ids: &[usize]) {
+  let size = atlas.get_size().clone();
+  let mut list = Vec::new();
+  for id in ids.iter() {
+    let (s, p) = atlas.get(*id).unwrap();
+    let mut s = s.clone();
+    align_size(atlas.get_align(), &mut s);
+    assert!(p.x + s.x <= size.x && p.y + s.y <= size.y);
+    // 宽或高为0的矩形不占用空间，可以和其它矩形重叠
+    if s.x > 0 && s.y > 0 {
+      list.push(Rect::new(p.x, p.y, s.x, s.y));
+    }
+  }
+  for i in 0..list.len() {
+    for j in i + 1..list.len() {
+      assert!(!list[i].intersects(&list[j]), "{:?} {:?}", list[i], list[j]);
+    }
+  }
+  assert_eq!(atlas.count(), ids.len());
+}
+
+// 随机添加、移除、扩大，返回添加成功的数量
+#[cfg(test)]
+fn fill_atlas<A: Atlas>(atlas: &mut A) -> usize {
+  let mut ids = Vec::new();
+  let mut added = 0;
+  let mut seed = 7usize;
+  let mut next = |n: usize| {
+    seed = (seed * 1103515245 + 12345) % 2147483648;
+    seed / 65536 % n
+  };
+  for round in 0..3 {
+    // 宽或高为0的矩形(如空格的字形)
+    for size in [Vector2 { x: 0, y: 12 }, Vector2 { x: 7, y: 0 }, Vector2 { x: 0, y: 0 }].iter() {
+      let id = atlas.add(size.clone());
+      assert!(id > 0);
+      assert_eq!(atlas.get(id).map(|(s, _)| s.clone()), Some(size.clone()));
+      ids.push(id);
+    }
+    for _ in 0..200 {
+      let id = atlas.add(Vector2 { x: next(40) + 1, y: next(40) + 1 });
+      if id > 0 {
+        ids.push(id);
+        added += 1;
+      }
+    }
+    check_atlas(atlas, &ids);
+    let mut i = 0;
+    while i < ids.len() {
+      if next(2) == 0 {
+        let id = ids.swap_remove(i);
+        let (s, p) = atlas.get(id).map(|(s, p)| (s.clone(), p.clone())).unwrap();
+        assert_eq!(atlas.remove(id), (s, p));
+      } else {
+        i += 1;
+      }
+    }
+    check_atlas(atlas, &ids);
+    if round == 1 {
+      assert!(atlas.extends(Vector2 { x: 384, y: 300 }));
+      assert!(!atlas.extends(Vector2 { x: 100, y: 100 }));
+      assert_eq!(atlas.get_size(), &Vector2 { x: 384, y: 304 });
+    }
+  }
+  for id in ids.drain(..) {
+    atlas.remove(id);
+  }
+  assert_eq!(atlas.count(), 0);
+  // 全部移除后，可以放下和总大小一样的矩形
+  let size = atlas.get_size().clone();
+  let id = atlas.add(size);
+  assert!(id > 0);
+  assert_eq!(atlas.get(id).map(|(_, p)| p.clone()), Some(Point2 { x: 0, y: 0 }));
+  added
+}
+
+#[test]
+fn test_atlas() {
+  use guillotine::{FitHeuristic, SplitHeuristic};
+  let size = Vector2 { x: 256, y: 256 };
+  let mut counts = vec![fill_atlas(&mut RectMap::new(size, 8))];
+  for h in [max_rects::Heuristic::BestShortSideFit, max_rects::Heuristic::BestLongSideFit,
+    max_rects::Heuristic::BestAreaFit, max_rects::Heuristic::BottomLeft, max_rects::Heuristic::ContactPoint].iter() {
+    counts.push(fill_atlas(&mut MaxRects::new(size, 8, *h)));
+  }
+  for h in [skyline::Heuristic::BottomLeft, skyline::Heuristic::MinWaste].iter() {
+    counts.push(fill_atlas(&mut Skyline::new(size, 8, *h)));
+  }
+  for f in [FitHeuristic::BestAreaFit, FitHeuristic::BestShortSideFit, FitHeuristic::BestLongSideFit, FitHeuristic::WorstAreaFit].iter() {
+    for s in [SplitHeuristic::ShorterLeftoverAxis, SplitHeuristic::LongerLeftoverAxis, SplitHeuristic::ShorterAxis, SplitHeuristic::LongerAxis].iter() {
+      counts.push(fill_atlas(&mut Guillotine::new(size, 8, *f, *s)));
+    }
+  }
+  assert!(counts.iter().all(|c| *c > 0));
+}
+
+#[test]
+fn test_repack() {
+  let mut map = RectMap::new(Vector2 { x: 128, y: 128 }, 8);
+  map.set_rotate(true);
+  let mut ids = Vec::new();
+  for i in 0..64 {
+    let padding = i % 3;
+    ids.push(map.add_with_padding(Vector2 { x: 10 + i % 5, y: 3 + i % 4 }, padding));
+  }
+  assert!(ids.iter().all(|id| *id > 0));
+  // 间隔移除，空闲区域分散，放不下大矩形
+  let mut live = Vec::new();
+  for (i, id) in ids.iter().enumerate() {
+    if i % 2 == 0 {
+      map.remove(*id);
+    } else {
+      live.push((*id, map.get_info(*id).unwrap()));
+    }
+  }
+  assert_eq!(map.add(Vector2 { x: 64, y: 64 }), 0);
+  let moves = map.repack().unwrap();
+  assert!(moves.len() > 0);
+  assert_eq!(map.count(), live.len());
+  let mut rects = Vec::new();
+  for &(id, ref info) in live.iter() {
+    let new = map.get_info(id).unwrap();
+    assert_eq!((new.size, new.padding, new.rotated), (info.size, info.padding, info.rotated));
+    match moves.iter().find(|m| m.id == id) {
+      Some(m) => {
+        assert_eq!((m.size, m.old_pos, m.new_pos), (info.placed_size(), info.pos, new.pos));
+      }
+      _ => assert_eq!(new.pos, info.pos),
+    }
+    let s = new.placed_size();
+    let p = new.padding;
+    rects.push(Rect::new(new.pos.x - p, new.pos.y - p, s.x + p * 2, s.y + p * 2));
+  }
+  for i in 0..rects.len() {
+    assert!(rects[i].right() <= 128 && rects[i].bottom() <= 128);
+    for j in i + 1..rects.len() {
+      assert!(!rects[i].intersects(&rects[j]));
+    }
+  }
+  // 整理后可以放下大矩形，移除所有矩形后恢复为空
+  let big = map.add(Vector2 { x: 64, y: 64 });
+  assert!(big > 0);
+  assert!(live.iter().all(|r| r.0 != big));
+  map.remove(big);
+  for &(id, _) in live.iter() {
+    map.remove(id);
+  }
+  assert_eq!(map.count(), 0);
+  assert!(map.add(Vector2 { x: 128, y: 128 }) > 0);
+}
+
+#[test]
+fn test_stats() {
+  let mut map = RectMap::new(Vector2 { x: 64, y: 64 }, 8);
+  assert_eq!((map.used_area(), map.free_area()), (0, 4096));
+  assert_eq!(map.max_free_rect(), Vector2 { x: 64, y: 64 });
+  assert_eq!(map.fragmentation(), 0.0);
+  assert_eq!(map.iter().count(), 0);
+  let a = map.add(Vector2 { x: 30, y: 10 });
+  let b = map.add_with_padding(Vector2 { x: 5, y: 5 }, 2);
+  let c = map.add(Vector2 { x: 16, y: 16 });
+  // 对齐后分别占用32*16、16*16、16*16
+  assert_eq!(map.used_area(), 512 + 256 + 256);
+  assert_eq!(map.free_area(), 4096 - 1024);
+  assert_eq!(map.max_free_rect(), Vector2 { x: 64, y: 48 });
+  assert_eq!(map.fragmentation(), 0.0);
+  let mut list: Vec<_> = map.iter().map(|(id, s, p)| (id, *s, *p)).collect();
+  list.sort_by_key(|r| r.0);
+  let mut expect = vec![
+    (a, Vector2 { x: 30, y: 10 }, Point2 { x: 0, y: 0 }),
+    (b, Vector2 { x: 5, y: 5 }, Point2 { x: 34, y: 2 }),
+    (c, Vector2 { x: 16, y: 16 }, Point2 { x: 48, y: 0 }),
+  ];
+  expect.sort_by_key(|r| r.0);
+  assert_eq!(list, expect);
+  // 移除中间的矩形后空闲区域不连续
+  map.remove(b);
+  assert_eq!(map.free_area(), 4096 - 768);
+  assert_eq!(map.max_free_rect(), Vector2 { x: 64, y: 48 });
+  assert_eq!(map.fragmentation(), 1.0 - 3072.0 / 3328.0);
+  assert_eq!(map.iter().count(), 2);
+}
+
+#[test]
+fn test_grow() {
+  let mut map = RectMap::new(Vector2 { x: 32, y: 32 }, 8);
+  // 不自动扩大
+  assert_eq!(map.try_add(Vector2 { x: 40, y: 8 }), Err(AddError::TooLarge));
+  assert_eq!(map.try_add(Vector2 { x: 32, y: 32 }).map(|r| r.1), Ok(None));
+  assert_eq!(map.try_add(Vector2 { x: 8, y: 8 }), Err(AddError::Full(None)));
+  assert_eq!(map.get_size(), &Vector2 { x: 32, y: 32 });
+  // 按2的幂扩大，先扩大较短的边
+  map.set_grow(Grow::PowerOfTwo, Vector2 { x: 100, y: 70 });
+  assert_eq!(map.get_grow(), (Grow::PowerOfTwo, &Vector2 { x: 96, y: 64 }));
+  let (id, size) = map.try_add(Vector2 { x: 8, y: 8 }).unwrap();
+  assert_eq!(size, Some(Vector2 { x: 64, y: 32 }));
+  assert_eq!(map.get(id), Some((&Vector2 { x: 8, y: 8 }, &Point2 { x: 32, y: 0 })));
+  let (_, size) = map.try_add(Vector2 { x: 64, y: 32 }).unwrap();
+  assert_eq!(size, Some(Vector2 { x: 64, y: 64 }));
+  // 宽度扩大到最大大小
+  let (_, size) = map.try_add(Vector2 { x: 32, y: 64 }).unwrap();
+  assert_eq!(size, Some(Vector2 { x: 96, y: 64 }));
+  assert_eq!(map.try_add(Vector2 { x: 8, y: 72 }), Err(AddError::TooLarge));
+  // 达到最大大小后不再扩大，填满后返回Full
+  while let Ok((_, size)) = map.try_add(Vector2 { x: 8, y: 8 }) {
+    assert_eq!(size, None);
+  }
+  assert_eq!(map.used_area(), 96 * 64);
+  assert_eq!(map.try_add(Vector2 { x: 8, y: 8 }), Err(AddError::Full(None)));
+  // 按固定步长扩大，一次可能扩大多次
+  let mut map = RectMap::new(Vector2 { x: 16, y: 16 }, 4);
+  map.set_grow(Grow::Step(8), Vector2 { x: 64, y: 64 });
+  let (id, size) = map.try_add(Vector2 { x: 30, y: 20 }).unwrap();
+  assert_eq!(size, Some(Vector2 { x: 32, y: 24 }));
+  assert_eq!(map.get(id), Some((&Vector2 { x: 30, y: 20 }, &Point2 { x: 0, y: 0 })));
+  assert_eq!(map.try_add(Vector2 { x: 4, y: 4 }).unwrap().1, None);
+  // 允许旋转时，旋转后能放下就不算太大
+  map.set_rotate(true);
+  assert!(map.try_add(Vector2 { x: 64, y: 8 }).is_ok());
+  assert_eq!(map.try_add(Vector2 { x: 65, y: 8 }), Err(AddError::TooLarge));
+  // 扩大到最大大小后空闲区域只剩8宽的L形，仍放不下，返回扩大后的大小
+  let mut map = RectMap::new(Vector2 { x: 16, y: 16 }, 4);
+  map.set_grow(Grow::Step(8), Vector2 { x: 24, y: 24 });
+  assert!(map.add(Vector2 { x: 16, y: 16 }) > 0);
+  assert_eq!(map.try_add(Vector2 { x: 12, y: 12 }), Err(AddError::Full(Some(Vector2 { x: 24, y: 24 }))));
+  assert_eq!(map.get_size(), &Vector2 { x: 24, y: 24 });
+  assert_eq!(map.try_add(Vector2 { x: 12, y: 12 }), Err(AddError::Full(None)));
+  assert_eq!(map.try_add(Vector2 { x: 8, y: 8 }).map(|r| r.1), Ok(None));
+}
+
+#[test]
+fn test_shrink() {
+  let mut map = RectMap::new(Vector2 { x: 64, y: 64 }, 8);
+  let ids: Vec<usize> = (0..16).map(|_| map.add(Vector2 { x: 16, y: 16 })).collect();
+  // 只留下最后一行的两个矩形
+  for id in ids[..14].iter() {
+    map.remove(*id);
+  }
+  let live: Vec<_> = ids[14..].iter().map(|id| (*id, map.get_info(*id).unwrap())).collect();
+  let moves = map.shrink_to_fit();
+  // 面积相同时选择较窄的
+  assert_eq!(map.get_size(), &Vector2 { x: 16, y: 32 });
+  assert_eq!(moves.len(), 2);
+  for m in moves.iter() {
+    let info = &live.iter().find(|r| r.0 == m.id).unwrap().1;
+    assert_eq!((m.size, m.old_pos), (info.size, info.pos));
+    assert_eq!(map.get(m.id), Some((&info.size, &m.new_pos)));
+    assert!(m.new_pos.x + m.size.x <= 16 && m.new_pos.y + m.size.y <= 32);
+  }
+  assert_ne!(moves[0].new_pos, moves[1].new_pos);
+  // 已经最小，不再移动
+  assert!(map.shrink_to_fit().is_empty());
+  assert_eq!(map.add(Vector2 { x: 8, y: 8 }), 0);
+  // 缩小到放不下则不修改，扩大时矩形不移动
+  assert_eq!(map.try_resize(Vector2 { x: 16, y: 16 }), None);
+  assert_eq!(map.get_size(), &Vector2 { x: 16, y: 32 });
+  assert_eq!(map.try_resize(Vector2 { x: 20, y: 40 }), Some(Vec::new()));
+  assert_eq!(map.get_size(), &Vector2 { x: 24, y: 40 });
+  let moves = map.try_resize(Vector2 { x: 32, y: 16 }).unwrap();
+  assert_eq!(map.get_size(), &Vector2 { x: 32, y: 16 });
+  assert_eq!(moves.len(), 1);
+  assert_eq!(map.add(Vector2 { x: 8, y: 8 }), 0);
+  // 移除后id仍然有效，全部移除后缩小到一个对齐大小
+  map.remove(ids[14]);
+  map.remove(ids[15]);
+  assert!(map.shrink_to_fit().is_empty());
+  assert_eq!(map.get_size(), &Vector2 { x: 8, y: 8 });
+  assert_eq!(map.count(), 0);
+}
+
+#[test]
+fn test_pack() {
+  let mut seed = 11usize;
+  let mut next = |n: usize| {
+    seed = (seed * 1103515245 + 12345) % 2147483648;
+    seed / 65536 % n
+  };
+  let sizes: Vec<_> = (0..40).map(|_| Vector2 { x: next(30) + 2, y: next(30) + 2 }).collect();
+  let max = Vector2 { x: 512, y: 512 };
+  // 检查所有矩形都放入，大小不变，且互不重叠
+  let check = |map: &RectMap, infos: &Vec<(usize, RectInfo)>| {
+    assert_eq!(map.count(), sizes.len());
+    let size = map.get_size();
+    let mut rects = Vec::new();
+    for (s, &(id, ref info)) in sizes.iter().zip(infos.iter()) {
+      assert_eq!(&info.size, s);
+      assert_eq!(map.get_info(id).as_ref(), Some(info));
+      let (p, ps) = (info.padding, info.placed_size());
+      let r = Rect::new(info.pos.x - p, info.pos.y - p, ps.x + p * 2, ps.y + p * 2);
+      assert!(r.right() <= size.x && r.bottom() <= size.y);
+      rects.push(r);
+    }
+    for i in 0..rects.len() {
+      for j in i + 1..rects.len() {
+        assert!(!rects[i].intersects(&rects[j]));
+      }
+    }
+  };
+  let (map, infos) = RectMap::pack(&sizes, Vector2 { x: 200, y: 200 }, 4, 1, false, Bin::Fixed).unwrap();
+  assert_eq!(map.get_size(), &Vector2 { x: 200, y: 200 });
+  assert_eq!((map.get_padding(), map.get_rotate()), (1, false));
+  check(&map, &infos);
+  // 结果和输入顺序无关
+  let mut rev = sizes.clone();
+  rev.reverse();
+  let (map2, _) = RectMap::pack(&rev, Vector2 { x: 200, y: 200 }, 4, 1, false, Bin::Fixed).unwrap();
+  let mut a: Vec<_> = map.iter().map(|(_, s, p)| (s.x, s.y, p.x, p.y)).collect();
+  let mut b: Vec<_> = map2.iter().map(|(_, s, p)| (s.x, s.y, p.x, p.y)).collect();
+  a.sort();
+  b.sort();
+  assert_eq!(a, b);
+  // 最小的正方形，再小一个对齐大小就放不下
+  let (map, infos) = RectMap::pack(&sizes, max, 4, 1, true, Bin::Square).unwrap();
+  let side = map.get_size().x;
+  assert_eq!(map.get_size().y, side);
+  assert!(infos.iter().any(|r| r.1.rotated));
+  check(&map, &infos);
+  assert!(RectMap::pack(&sizes, Vector2 { x: side - 4, y: side - 4 }, 4, 1, true, Bin::Fixed).is_none());
+  // 2的幂
+  let (map, infos) = RectMap::pack(&sizes, max, 4, 1, false, Bin::PowerOfTwo).unwrap();
+  let size = map.get_size().clone();
+  assert!(size.x.is_power_of_two() && size.y.is_power_of_two());
+  assert!(size.x * size.y <= 256 * 256);
+  check(&map, &infos);
+  // 放不下
+  assert!(RectMap::pack(&sizes, Vector2 { x: 64, y: 64 }, 4, 1, false, Bin::Square).is_none());
+  assert!(RectMap::pack(&sizes, Vector2 { x: 64, y: 64 }, 4, 1, false, Bin::PowerOfTwo).is_none());
+  // 没有矩形时为一个对齐大小的正方形
+  let (map, infos) = RectMap::pack(&[], Vector2 { x: 16, y: 16 }, 4, 0, false, Bin::Square).unwrap();
+  assert_eq!((map.count(), infos.len()), (0, 0));
+  assert_eq!(map.get_size(), &Vector2 { x: 4, y: 4 });
+  // 最大大小先向下对齐
+  let (map, _) = RectMap::pack(&sizes, Vector2 { x: 202, y: 203 }, 4, 1, false, Bin::Fixed).unwrap();
+  assert_eq!(map.get_size(), &Vector2 { x: 200, y: 200 });
+}
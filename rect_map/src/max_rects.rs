@@ -0,0 +1,177 @@
+//! MaxRects装箱算法
+//! 空闲区域用一组可以互相重叠的最大矩形表示，放入矩形后切分所有相交的空闲矩形，并去掉被包含的空闲矩形。
+//! 装箱率高，适合离线打包sprite图集，空闲矩形较多时添加会比较慢。
+
+use cgmath::{Point2, Vector2};
+
+use slab::Slab;
+
+use {align_size, merge_rects, prune_rects, Atlas, Rect};
+
+/// 选择空闲矩形的策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Heuristic {
+  BestShortSideFit, // 短边剩余最少
+  BestLongSideFit,  // 长边剩余最少
+  BestAreaFit,      // 面积剩余最少
+  BottomLeft,       // 尽量靠上靠左
+  ContactPoint,     // 和已有矩形及边界接触的边长最多
+}
+
+/// MaxRects矩形表
+pub struct MaxRects {
+  size: Vector2<usize>,
+  align: usize,         // 对齐大小
+  heuristic: Heuristic, // 选择空闲矩形的策略
+  free: Vec<Rect>,      // 空闲矩形
+  rects: Slab<(Vector2<usize>, Point2<usize>)>, // 已分配的矩形大小及位置
+}
+
+impl MaxRects {
+  pub fn new(mut size: Vector2<usize>, align: usize, heuristic: Heuristic) -> Self {
+    align_size(align, &mut size);
+    MaxRects {
+      size: size,
+      align: align,
+      heuristic: heuristic,
+      free: vec![Rect::new(0, 0, size.x, size.y)],
+      rects: Slab::new(),
+    }
+  }
+  // 获得选择空闲矩形的策略
+  pub fn get_heuristic(&self) -> Heuristic {
+    self.heuristic
+  }
+  // 计算放入空闲矩形的分数，越小越好
+  fn score(&self, free: &Rect, w: usize, h: usize) -> (usize, usize) {
+    let (lw, lh) = (free.w - w, free.h - h);
+    let (short, long) = if lw < lh { (lw, lh) } else { (lh, lw) };
+    match self.heuristic {
+      Heuristic::BestShortSideFit => (short, long),
+      Heuristic::BestLongSideFit => (long, short),
+      Heuristic::BestAreaFit => (free.w * free.h - w * h, short),
+      Heuristic::BottomLeft => (free.y + h, free.x),
+      Heuristic::ContactPoint => {
+        let r = Rect::new(free.x, free.y, w, h);
+        (usize::max_value() - self.contact(&r), 0)
+      }
+    }
+  }
+  // 计算矩形和边界及已分配矩形接触的边长
+  fn contact(&self, r: &Rect) -> usize {
+    let mut c = 0;
+    if r.x == 0 || r.right() == self.size.x {
+      c += r.h;
+    }
+    if r.y == 0 || r.bottom() == self.size.y {
+      c += r.w;
+    }
+    for (_, &(ref size, ref pos)) in self.rects.iter() {
+      let mut s = size.clone();
+      align_size(self.align, &mut s);
+      c += r.contact(&Rect::new(pos.x, pos.y, s.x, s.y));
+    }
+    c
+  }
+  // 放入矩形后，切分和其相交的空闲矩形
+  fn split(&mut self, used: &Rect) {
+    let mut i = 0;
+    while i < self.free.len() {
+      let f = self.free[i];
+      if !f.intersects(used) {
+        i += 1;
+        continue;
+      }
+      self.free.swap_remove(i);
+      if used.x > f.x {
+        self.free.push(Rect::new(f.x, f.y, used.x - f.x, f.h));
+      }
+      if used.right() < f.right() {
+        self.free.push(Rect::new(used.right(), f.y, f.right() - used.right(), f.h));
+      }
+      if used.y > f.y {
+        self.free.push(Rect::new(f.x, f.y, f.w, used.y - f.y));
+      }
+      if used.bottom() < f.bottom() {
+        self.free.push(Rect::new(f.x, used.bottom(), f.w, f.bottom() - used.bottom()));
+      }
+    }
+    prune_rects(&mut self.free);
+  }
+}
+
+impl Atlas for MaxRects {
+  fn get_size(&self) -> &Vector2<usize> {
+    &self.size
+  }
+  fn get_align(&self) -> usize {
+    self.align
+  }
+  fn count(&self) -> usize {
+    self.rects.len()
+  }
+  fn add(&mut self, size: Vector2<usize>) -> usize {
+    let mut asize = size.clone();
+    align_size(self.align, &mut asize);
+    let mut best = None;
+    for f in self.free.iter() {
+      if f.w < asize.x || f.h < asize.y {
+        continue;
+      }
+      let score = self.score(f, asize.x, asize.y);
+      match best {
+        Some((s, _)) if s <= score => (),
+        _ => best = Some((score, Rect::new(f.x, f.y, asize.x, asize.y))),
+      }
+    }
+    match best {
+      Some((_, used)) => {
+        self.split(&used);
+        self.rects.insert((size, Point2 { x: used.x, y: used.y }))
+      }
+      _ => 0,
+    }
+  }
+  fn get(&self, id: usize) -> Option<(&Vector2<usize>, &Point2<usize>)> {
+    self.rects.get(id).map(|&(ref size, ref pos)| (size, pos))
+  }
+  fn remove(&mut self, id: usize) -> (Vector2<usize>, Point2<usize>) {
+    let (size, pos) = self.rects.remove(id);
+    if self.rects.len() == 0 {
+      self.free = vec![Rect::new(0, 0, self.size.x, self.size.y)];
+    } else {
+      let mut asize = size.clone();
+      align_size(self.align, &mut asize);
+      self.free.push(Rect::new(pos.x, pos.y, asize.x, asize.y));
+      merge_rects(&mut self.free);
+      prune_rects(&mut self.free);
+    }
+    (size, pos)
+  }
+  fn extends(&mut self, mut size: Vector2<usize>) -> bool {
+    align_size(self.align, &mut size);
+    let w = if self.size.x > size.x { self.size.x } else { size.x };
+    let h = if self.size.y > size.y { self.size.y } else { size.y };
+    if w == self.size.x && h == self.size.y {
+      return false;
+    }
+    // 靠右及靠下的空闲矩形延伸到新的边界
+    for f in self.free.iter_mut() {
+      if f.right() == self.size.x {
+        f.w = w - f.x;
+      }
+      if f.bottom() == self.size.y {
+        f.h = h - f.y;
+      }
+    }
+    if w > self.size.x {
+      self.free.push(Rect::new(self.size.x, 0, w - self.size.x, h));
+    }
+    if h > self.size.y {
+      self.free.push(Rect::new(0, self.size.y, w, h - self.size.y));
+    }
+    self.size = Vector2 { x: w, y: h };
+    prune_rects(&mut self.free);
+    true
+  }
+}
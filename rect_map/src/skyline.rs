@@ -0,0 +1,234 @@
+//! Skyline装箱算法
+//! 用一条从左到右的天际线记录已占用区域的上沿，矩形放在天际线上。
+//! 放入时天际线下方浪费的区域及移除的矩形记录在浪费列表中，添加时优先从浪费列表中分配。
+//! 添加速度快，适合动态的字形缓存。
+
+use cgmath::{Point2, Vector2};
+
+use slab::Slab;
+
+use {align_size, merge_rects, Atlas, Rect};
+
+/// 选择放置位置的策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Heuristic {
+  BottomLeft, // 放置后的上沿尽量低，其次天际线段尽量窄
+  MinWaste,   // 放置后天际线下方浪费的面积最少，其次上沿尽量低
+}
+
+/// Skyline矩形表
+pub struct Skyline {
+  size: Vector2<usize>,
+  align: usize,         // 对齐大小
+  heuristic: Heuristic, // 选择放置位置的策略
+  line: Vec<Segment>,   // 天际线，按x从小到大排列
+  waste: Vec<Rect>,     // 浪费的空闲矩形
+  rects: Slab<(Vector2<usize>, Point2<usize>)>, // 已分配的矩形大小及位置
+}
+
+// 天际线段
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+  x: usize,
+  y: usize,
+  w: usize,
+}
+
+impl Skyline {
+  pub fn new(mut size: Vector2<usize>, align: usize, heuristic: Heuristic) -> Self {
+    align_size(align, &mut size);
+    Skyline {
+      size: size,
+      align: align,
+      heuristic: heuristic,
+      line: vec![Segment { x: 0, y: 0, w: size.x }],
+      waste: Vec::new(),
+      rects: Slab::new(),
+    }
+  }
+  // 获得选择放置位置的策略
+  pub fn get_heuristic(&self) -> Heuristic {
+    self.heuristic
+  }
+  // 从第i段开始放置宽w高h的矩形，返回放置的y及浪费的面积，放不下返回None
+  fn fit(&self, i: usize, w: usize, h: usize) -> Option<(usize, usize)> {
+    let x = self.line[i].x;
+    if x + w > self.size.x {
+      return None;
+    }
+    let mut y = 0;
+    let mut j = i;
+    while self.line[j].x < x + w {
+      if self.line[j].y > y {
+        y = self.line[j].y;
+      }
+      j += 1;
+      if j == self.line.len() {
+        break;
+      }
+    }
+    if y + h > self.size.y {
+      return None;
+    }
+    let mut waste = 0;
+    for s in self.line[i..j].iter() {
+      waste += (y - s.y) * overlap(s, x, w);
+    }
+    Some((y, waste))
+  }
+  // 在天际线上放置矩形，天际线下方浪费的区域放入浪费列表
+  fn place(&mut self, i: usize, y: usize, w: usize, h: usize) {
+    let x = self.line[i].x;
+    let mut j = i;
+    while j < self.line.len() && self.line[j].x < x + w {
+      let s = self.line[j];
+      if s.y < y {
+        self.waste.push(Rect::new(s.x.max(x), s.y, overlap(&s, x, w), y - s.y));
+      }
+      j += 1;
+    }
+    // 最后一段可能只被覆盖了一部分
+    let last = self.line[j - 1];
+    let tail = if last.x + last.w > x + w {
+      Some(Segment {
+        x: x + w,
+        y: last.y,
+        w: last.x + last.w - x - w,
+      })
+    } else {
+      None
+    };
+    self.line.splice(i..j, Some(Segment { x: x, y: y + h, w: w }).into_iter().chain(tail));
+    // 合并相同高度的相邻段
+    let mut k = 1;
+    while k < self.line.len() {
+      if self.line[k - 1].y == self.line[k].y {
+        self.line[k - 1].w += self.line[k].w;
+        self.line.remove(k);
+      } else {
+        k += 1;
+      }
+    }
+  }
+  // 从浪费列表中分配，选择面积最接近的矩形，剩余部分沿较短的剩余边切分
+  fn add_waste(&mut self, w: usize, h: usize) -> Option<Point2<usize>> {
+    let mut best = None;
+    for (i, r) in self.waste.iter().enumerate() {
+      if r.w < w || r.h < h {
+        continue;
+      }
+      let area = r.w * r.h;
+      match best {
+        Some((a, _)) if a <= area => (),
+        _ => best = Some((area, i)),
+      }
+    }
+    let i = match best {
+      Some((_, i)) => i,
+      _ => return None,
+    };
+    let r = self.waste.swap_remove(i);
+    let horizontal = r.w - w <= r.h - h;
+    if r.h > h {
+      self.waste.push(Rect::new(r.x, r.y + h, if horizontal { r.w } else { w }, r.h - h));
+    }
+    if r.w > w {
+      self.waste.push(Rect::new(r.x + w, r.y, r.w - w, if horizontal { h } else { r.h }));
+    }
+    Some(Point2 { x: r.x, y: r.y })
+  }
+}
+
+impl Atlas for Skyline {
+  fn get_size(&self) -> &Vector2<usize> {
+    &self.size
+  }
+  fn get_align(&self) -> usize {
+    self.align
+  }
+  fn count(&self) -> usize {
+    self.rects.len()
+  }
+  fn add(&mut self, size: Vector2<usize>) -> usize {
+    let mut asize = size.clone();
+    align_size(self.align, &mut asize);
+    // 宽或高为0的矩形(如空格的字形)不占用空间，不修改天际线
+    if asize.x == 0 || asize.y == 0 {
+      return self.rects.insert((size, Point2 { x: 0, y: 0 }));
+    }
+    if let Some(pos) = self.add_waste(asize.x, asize.y) {
+      return self.rects.insert((size, pos));
+    }
+    let mut best = None;
+    for i in 0..self.line.len() {
+      let (y, waste) = match self.fit(i, asize.x, asize.y) {
+        Some(r) => r,
+        _ => continue,
+      };
+      let score = match self.heuristic {
+        Heuristic::BottomLeft => (y + asize.y, self.line[i].w),
+        Heuristic::MinWaste => (waste, y + asize.y),
+      };
+      match best {
+        Some((s, _, _)) if s <= score => (),
+        _ => best = Some((score, i, y)),
+      }
+    }
+    match best {
+      Some((_, i, y)) => {
+        let x = self.line[i].x;
+        self.place(i, y, asize.x, asize.y);
+        self.rects.insert((size, Point2 { x: x, y: y }))
+      }
+      _ => 0,
+    }
+  }
+  fn get(&self, id: usize) -> Option<(&Vector2<usize>, &Point2<usize>)> {
+    self.rects.get(id).map(|&(ref size, ref pos)| (size, pos))
+  }
+  fn remove(&mut self, id: usize) -> (Vector2<usize>, Point2<usize>) {
+    let (size, pos) = self.rects.remove(id);
+    if self.rects.len() == 0 {
+      self.line = vec![Segment { x: 0, y: 0, w: self.size.x }];
+      self.waste.clear();
+    } else {
+      let mut asize = size.clone();
+      align_size(self.align, &mut asize);
+      if asize.x > 0 && asize.y > 0 {
+        self.waste.push(Rect::new(pos.x, pos.y, asize.x, asize.y));
+        merge_rects(&mut self.waste);
+      }
+    }
+    (size, pos)
+  }
+  fn extends(&mut self, mut size: Vector2<usize>) -> bool {
+    align_size(self.align, &mut size);
+    let w = if self.size.x > size.x { self.size.x } else { size.x };
+    let h = if self.size.y > size.y { self.size.y } else { size.y };
+    if w == self.size.x && h == self.size.y {
+      return false;
+    }
+    if w > self.size.x {
+      let last = self.line.len() - 1;
+      if self.line[last].y == 0 {
+        self.line[last].w += w - self.size.x;
+      } else {
+        self.line.push(Segment {
+          x: self.size.x,
+          y: 0,
+          w: w - self.size.x,
+        });
+      }
+    }
+    self.size = Vector2 { x: w, y: h };
+    true
+  }
+}
+
+// 天际线段和[x, x+w)重叠的宽度
+#[inline]
+fn overlap(s: &Segment, x: usize, w: usize) -> usize {
+  let l = if s.x > x { s.x } else { x };
+  let r = if s.x + s.w < x + w { s.x + s.w } else { x + w };
+  r - l
+}